@@ -0,0 +1,330 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use time::Date;
+
+use crate::journal;
+
+// Old entries can be bundled into gzipped tar files in the archive
+// directory of the journal, one per month (named like 2021-10.tar.gz)
+// or one per year (named like 2021.tar.gz). Entries are stored exactly
+// as they were on disk, so encrypted entries stay encrypted.
+pub const ARCHIVE_DIR: &str = "archive";
+
+const ARCHIVE_SUFFIX: &str = ".tar.gz";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Month,
+    Year,
+}
+
+// A set of entries, and the archive file they belong in.
+#[derive(Debug, PartialEq)]
+pub struct Bundle {
+    pub archive: String,
+    pub names: Vec<String>,
+}
+
+fn period_name(date: Date, period: Period) -> String {
+    match period {
+        Period::Month => format!("{:04}-{:02}", date.year(), date.month() as u8),
+        Period::Year => format!("{:04}", date.year()),
+    }
+}
+
+fn archive_filename(journal_dir: &str, period_name: &str) -> String {
+    let path = Path::new(journal_dir)
+        .join(ARCHIVE_DIR)
+        .join(format!("{}{}", period_name, ARCHIVE_SUFFIX));
+    path.to_str().unwrap().to_string()
+}
+
+// Returns the filenames of all of the archives in journal_dir, in order.
+pub fn archive_filenames(journal_dir: &str) -> Result<Vec<String>, io::Error> {
+    let archive_dir = Path::new(journal_dir).join(ARCHIVE_DIR);
+    let dir = match fs::read_dir(&archive_dir) {
+        Ok(d) => d,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut filenames = vec![];
+    for dirent in dir {
+        let dirent = dirent?;
+        if let Some(name) = dirent.file_name().to_str() {
+            if name.ends_with(ARCHIVE_SUFFIX) {
+                filenames.push(archive_dir.join(name).to_str().unwrap().to_string());
+            }
+        }
+    }
+    filenames.sort();
+
+    Ok(filenames)
+}
+
+// Returns the contents of every entry in the archive file, by name.
+// Entries larger than max_length are an error.
+pub fn read_archive(
+    filename: &str,
+    max_length: usize,
+) -> Result<BTreeMap<String, Vec<u8>>, io::Error> {
+    let mut ret = BTreeMap::new();
+    let mut tarball = tar::Archive::new(GzDecoder::new(File::open(filename)?));
+    for member in tarball.entries()? {
+        let mut member = member?;
+        // Anything that isn't named like an entry can't have been put
+        // there by coach, and could be trying to escape the journal.
+        let name = match member.path()?.to_str() {
+            Some(name) if journal::entry_date(name).is_some() => name.to_string(),
+            _ => continue,
+        };
+        if member.size() as usize >= max_length {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} in {} is longer than maximum length allowed",
+                    name, filename
+                ),
+            ));
+        }
+
+        let mut contents = vec![];
+        member.read_to_end(&mut contents)?;
+        ret.insert(name, contents);
+    }
+
+    Ok(ret)
+}
+
+fn write_archive(filename: &str, entries: &BTreeMap<String, Vec<u8>>) -> Result<(), io::Error> {
+    if let Some(parent) = Path::new(filename).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Write a new file and move it into place, so that an interrupted
+    // archive can't lose entries that were already in it.
+    let tmp_filename = format!("{}.tmp", filename);
+    let out = File::create(&tmp_filename)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    let now = UNIX_EPOCH.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+    for (name, contents) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(now);
+        header.set_cksum();
+        builder.append_data(&mut header, name, &contents[..])?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    fs::rename(&tmp_filename, filename)?;
+
+    Ok(())
+}
+
+// Finds the entries in journal_dir dated before before, grouped
+// by the archive they should be moved to.
+pub fn plan_archive(
+    journal_dir: &str,
+    before: Date,
+    period: Period,
+) -> Result<Vec<Bundle>, io::Error> {
+    let mut bundles: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in journal::entry_names(journal_dir)? {
+        let date = journal::entry_date(&name).unwrap();
+        if date < before {
+            let archive = archive_filename(journal_dir, &period_name(date, period));
+            bundles.entry(archive).or_default().push(name);
+        }
+    }
+
+    Ok(bundles
+        .into_iter()
+        .map(|(archive, names)| Bundle { archive, names })
+        .collect())
+}
+
+// Moves the entries in each bundle into its archive, adding to the
+// archive if it already exists.
+pub fn archive(journal_dir: &str, bundles: &[Bundle], max_length: usize) -> Result<(), io::Error> {
+    for bundle in bundles {
+        let mut contents = match read_archive(&bundle.archive, max_length) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        for name in bundle.names.iter() {
+            let filename = Path::new(journal_dir).join(name);
+            contents.insert(name.clone(), fs::read(filename)?);
+        }
+        write_archive(&bundle.archive, &contents)?;
+
+        for name in bundle.names.iter() {
+            fs::remove_file(Path::new(journal_dir).join(name))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Finds the archives in journal_dir and the entries in each of them. If
+// period_name is provided, like "2021" or "2021-10", only the archive for
+// that period is included.
+pub fn plan_unarchive(
+    journal_dir: &str,
+    period_name: Option<&str>,
+    max_length: usize,
+) -> Result<Vec<Bundle>, io::Error> {
+    let mut bundles = vec![];
+    for archive in archive_filenames(journal_dir)? {
+        if let Some(period_name) = period_name {
+            if archive != archive_filename(journal_dir, period_name) {
+                continue;
+            }
+        }
+
+        let names = read_archive(&archive, max_length)?.into_keys().collect();
+        bundles.push(Bundle { archive, names });
+    }
+
+    Ok(bundles)
+}
+
+// Moves the entries in each bundle out of its archive and back into
+// journal_dir, and removes the archive. Entries that already exist in
+// journal_dir are an error, and nothing is changed. Each archive is
+// unpacked into a temporary directory first, so that a failure part way
+// through writing it can't leave some of its entries both loose and
+// archived.
+pub fn unarchive(
+    journal_dir: &str,
+    bundles: &[Bundle],
+    max_length: usize,
+) -> Result<(), io::Error> {
+    for bundle in bundles {
+        for name in bundle.names.iter() {
+            if Path::new(journal_dir).join(name).exists() {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!(
+                        "{} is in {}, but it also exists outside of the archive",
+                        name, bundle.archive
+                    ),
+                ));
+            }
+        }
+    }
+
+    for bundle in bundles {
+        let unpacked = tempfile::tempdir_in(journal_dir)?;
+        let archived = read_archive(&bundle.archive, max_length)?;
+        for (name, contents) in archived.iter() {
+            let mut out = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(unpacked.path().join(name))?;
+            out.write_all(contents)?;
+            out.sync_all()?;
+        }
+        for name in archived.keys() {
+            fs::rename(
+                unpacked.path().join(name),
+                Path::new(journal_dir).join(name),
+            )?;
+        }
+        fs::remove_file(&bundle.archive)?;
+    }
+
+    Ok(())
+}
+
+// Returns the contents of the archived entry that would have been at
+// filename, if it hadn't been archived. Returns None if there isn't one.
+pub fn read_archived_file(filename: &str, max_length: usize) -> Result<Option<Vec<u8>>, io::Error> {
+    let path = Path::new(filename);
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let date = match journal::entry_date(name) {
+        Some(date) => date,
+        None => return Ok(None),
+    };
+    let journal_dir = match path.parent().and_then(|p| p.to_str()) {
+        Some("") | None => ".",
+        Some(dir) => dir,
+    };
+
+    for period in [Period::Month, Period::Year] {
+        let archive = archive_filename(journal_dir, &period_name(date, period));
+        match read_archive(&archive, max_length) {
+            Ok(mut contents) => {
+                if let Some(found) = contents.remove(name) {
+                    return Ok(Some(found));
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_dir = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("2021-10-30"), "#coach\n2021-10-30\n\n").unwrap();
+        fs::write(dir.path().join("2021-11-01"), "#coach\n2021-11-01\n\n").unwrap();
+        fs::write(dir.path().join("2022-01-01"), "#coach\n2022-01-01\n\n").unwrap();
+
+        let bundles = plan_archive(journal_dir, date!(2022 - 01 - 01), Period::Month).unwrap();
+        assert_eq!(2, bundles.len());
+        assert_eq!(vec!["2021-10-30"], bundles[0].names);
+        archive(journal_dir, &bundles, 1024).unwrap();
+        assert_eq!(
+            vec!["2022-01-01"],
+            journal::entry_names(journal_dir).unwrap()
+        );
+
+        let filename = dir.path().join("2021-10-30");
+        let contents = read_archived_file(filename.to_str().unwrap(), 1024).unwrap();
+        assert_eq!(Some(b"#coach\n2021-10-30\n\n".to_vec()), contents);
+
+        let bundles = plan_unarchive(journal_dir, Some("2021-11"), 1024).unwrap();
+        unarchive(journal_dir, &bundles, 1024).unwrap();
+        assert_eq!(
+            vec!["2021-11-01", "2022-01-01"],
+            journal::entry_names(journal_dir).unwrap()
+        );
+        assert_eq!(1, archive_filenames(journal_dir).unwrap().len());
+        // The temporary directory the archive was unpacked into is gone.
+        assert_eq!(3, fs::read_dir(journal_dir).unwrap().count());
+    }
+
+    #[test]
+    fn test_archive_by_year() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_dir = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("2021-10-30"), "#coach\n2021-10-30\n\n").unwrap();
+        fs::write(dir.path().join("2021-11-01"), "#coach\n2021-11-01\n\n").unwrap();
+
+        let bundles = plan_archive(journal_dir, date!(2022 - 01 - 01), Period::Year).unwrap();
+        assert_eq!(1, bundles.len());
+        assert!(bundles[0].archive.ends_with("2021.tar.gz"));
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use time::Date;
+
+use crate::crypt;
+use crate::entry::Task;
+use crate::files;
+use crate::index;
+use crate::journal::{entry_date, DATE_FORMAT};
+
+// Migrating moves incomplete tasks into the new entry, leaving nothing
+// behind to say how long they've been carried from day to day. So the day
+// each task was first seen is kept in a file that begins with a line
+// containing only CARRIED_MAGIC, followed by a line for each task holding
+// the hash of its message and the date, separated by a tab. Once a task is
+// done or cancelled, the day it was finished follows, after another tab.
+// Messages are only stored as hashes, but even a hash can be checked
+// against a guess, so nothing is kept for encrypted entries.
+const CARRIED_MAGIC: &str = "#coach-carried 1";
+
+struct Record {
+    first_seen: Date,
+    finished: Option<Date>,
+}
+
+pub struct Carried {
+    filename: String,
+    records: BTreeMap<u64, Record>,
+    changed: bool,
+}
+
+fn parse_records(text: &str) -> Option<BTreeMap<u64, Record>> {
+    let mut records = BTreeMap::new();
+    let remaining = text.strip_prefix(CARRIED_MAGIC)?.strip_prefix('\n')?;
+    for line in remaining.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let finished = match fields.len() {
+            2 => None,
+            3 => Some(entry_date(fields[2])?),
+            _ => return None,
+        };
+        records.insert(
+            u64::from_str_radix(fields[0], 16).ok()?,
+            Record {
+                first_seen: entry_date(fields[1])?,
+                finished,
+            },
+        );
+    }
+
+    Some(records)
+}
+
+impl Carried {
+    // Reads the dates stored in filename. If there are none there yet, or
+    // they can't be read, starts again with none.
+    pub fn open(filename: &str) -> Result<Carried, io::Error> {
+        let records = match fs::read_to_string(filename) {
+            Ok(text) => parse_records(&text).unwrap_or_default(),
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) if e.kind() == ErrorKind::InvalidData => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Carried {
+            filename: filename.to_string(),
+            records,
+            changed: false,
+        })
+    }
+
+    // The day the task with the message text was first seen, if it was
+    // being carried on date. A task that was finished before date and then
+    // written again is a different task.
+    pub fn first_seen(&self, text: &str, date: Date) -> Option<Date> {
+        let record = self.records.get(&index::hash(text.as_bytes()))?;
+        if record.first_seen <= date && record.finished.map_or(true, |finished| finished >= date) {
+            Some(record.first_seen)
+        } else {
+            None
+        }
+    }
+
+    // Notes that the task with the message text was in an entry on date,
+    // unless it was already seen earlier.
+    pub fn seen(&mut self, text: &str, date: Date) {
+        let hash = index::hash(text.as_bytes());
+        match self.records.get(&hash) {
+            Some(record) if record.finished.is_none() && record.first_seen <= date => {}
+            _ => {
+                self.records.insert(
+                    hash,
+                    Record {
+                        first_seen: date,
+                        finished: None,
+                    },
+                );
+                self.changed = true;
+            }
+        }
+    }
+
+    // Notes that the task with the message text was done or cancelled on
+    // date, if it was being carried.
+    pub fn finished(&mut self, text: &str, date: Date) {
+        if let Some(record) = self.records.get_mut(&index::hash(text.as_bytes())) {
+            if record.finished.is_none() {
+                record.finished = Some(date);
+                self.changed = true;
+            }
+        }
+    }
+
+    // Notes the tasks migrated on date from the entry in fromname: live
+    // ones were carried over, and dead ones were left behind finished.
+    // Nothing is noted if either entry is encrypted.
+    pub fn migrated(
+        &mut self,
+        fromname: &str,
+        live: &[Task],
+        dead: &[Task],
+        date: Date,
+        keys: &crypt::Keyring,
+    ) {
+        if keys.encrypts_new_entries() || files::is_encrypted_file(fromname).unwrap_or(true) {
+            return;
+        }
+
+        for task in live {
+            self.seen(task.text(), date);
+        }
+        for task in dead {
+            if !live.iter().any(|t| t.text() == task.text()) {
+                self.finished(task.text(), date);
+            }
+        }
+    }
+
+    // Writes the dates back to their file, if any of them have changed.
+    pub fn save(&mut self) -> Result<(), io::Error> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let path = Path::new(&self.filename);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut out = String::new();
+        out.push_str(CARRIED_MAGIC);
+        out.push('\n');
+        for (hash, record) in self.records.iter() {
+            out.push_str(&format!(
+                "{:x}\t{}",
+                hash,
+                record.first_seen.format(&DATE_FORMAT).unwrap()
+            ));
+            if let Some(finished) = record.finished {
+                out.push_str(&format!("\t{}", finished.format(&DATE_FORMAT).unwrap()));
+            }
+            out.push('\n');
+        }
+
+        let tmp_filename = format!("{}.tmp", self.filename);
+        let mut tmp = fs::File::create(&tmp_filename)?;
+        tmp.write_all(out.as_bytes())?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_filename, &self.filename)?;
+        self.changed = false;
+
+        Ok(())
+    }
+}
+
+// An incomplete task, with its index as shown by coach task, and how long
+// it's been carried.
+#[derive(Debug, PartialEq)]
+pub struct Stale<'a> {
+    pub index: usize,
+    pub task: &'a Task,
+    pub first_seen: Date,
+    pub days: i64,
+}
+
+// The incomplete tasks in an entry for date that were first seen more than
+// days before it. Tasks that were never migrated were first seen on date.
+pub fn stale<'a>(carried: &Carried, tasks: &'a [Task], date: Date, days: i64) -> Vec<Stale<'a>> {
+    tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, task)| task.is_incomplete())
+        .map(|(ix, task)| {
+            let first_seen = carried.first_seen(task.text(), date).unwrap_or(date);
+            Stale {
+                index: ix + 1,
+                task,
+                first_seen,
+                days: (date - first_seen).whole_days(),
+            }
+        })
+        .filter(|stale| stale.days > days)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use time::macros::date;
+
+    #[test]
+    fn test_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".coach").join("carried");
+        let filename = path.to_str().unwrap();
+
+        let mut carried = Carried::open(filename).unwrap();
+        carried.seen("carve pumpkin", date!(2021 - 10 - 20));
+        carried.seen("carve pumpkin", date!(2021 - 10 - 29));
+        carried.seen("buy candy", date!(2021 - 10 - 29));
+        carried.seen("costume", date!(2021 - 10 - 01));
+        carried.finished("costume", date!(2021 - 10 - 03));
+        carried.save().unwrap();
+
+        let mut carried = Carried::open(filename).unwrap();
+        let today = date!(2021 - 11 - 01);
+        assert_eq!(
+            Some(date!(2021 - 10 - 20)),
+            carried.first_seen("carve pumpkin", today)
+        );
+        assert_eq!(
+            Some(date!(2021 - 10 - 01)),
+            carried.first_seen("costume", date!(2021 - 10 - 03))
+        );
+        assert_eq!(None, carried.first_seen("costume", today));
+        carried.seen("costume", date!(2021 - 10 - 30));
+        assert_eq!(
+            Some(date!(2021 - 10 - 30)),
+            carried.first_seen("costume", today)
+        );
+
+        let entry = entry::parse(
+            "#coach\n2021-11-01\n\nTODO buy candy\nTODO carve pumpkin\nDONE costume\nTODO eat candy\n",
+        )
+        .unwrap();
+        let found = stale(&carried, &entry.tasks, date!(2021 - 11 - 01), 3);
+        assert_eq!(
+            vec![Stale {
+                index: 2,
+                task: &entry.tasks[1],
+                first_seen: date!(2021 - 10 - 20),
+                days: 12,
+            }],
+            found
+        );
+        assert_eq!(
+            2,
+            stale(&carried, &entry.tasks, date!(2021 - 11 - 01), 0).len()
+        );
+    }
+
+    #[test]
+    fn test_encrypted_entries_are_not_tracked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".coach").join("carried");
+        let filename = path.to_str().unwrap();
+        let key_path = dir.path().join("key");
+        fs::write(&key_path, "correct horse\n").unwrap();
+        let key_file = Some(key_path.to_str().unwrap().to_string());
+        let plain_keys = crypt::Keyring::new(key_file.clone(), false);
+        let encrypting_keys = crypt::Keyring::new(key_file, true);
+
+        let text = "#coach\n2021-10-31\n\nTODO carve pumpkin\nDONE buy candy\n\n";
+        let entry = entry::parse(text).unwrap();
+        let (live, dead): (Vec<Task>, Vec<Task>) =
+            entry.tasks.into_iter().partition(|t| t.is_incomplete());
+        let plain = dir.path().join("2021-10-31");
+        fs::write(&plain, text).unwrap();
+        let encrypted = dir.path().join("2021-10-30");
+        fs::write(&encrypted, plain_keys.encrypt(text.as_bytes()).unwrap()).unwrap();
+
+        let mut carried = Carried::open(filename).unwrap();
+        let date = date!(2021 - 10 - 31);
+        carried.migrated(encrypted.to_str().unwrap(), &live, &dead, date, &plain_keys);
+        carried.migrated(
+            plain.to_str().unwrap(),
+            &live,
+            &dead,
+            date,
+            &encrypting_keys,
+        );
+        carried.save().unwrap();
+        assert!(!path.exists());
+
+        carried.migrated(plain.to_str().unwrap(), &live, &dead, date, &plain_keys);
+        carried.save().unwrap();
+        assert_eq!(Some(date), carried.first_seen("carve pumpkin", date));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains(&format!("{:x}", index::hash(b"carve pumpkin"))));
+    }
+}
@@ -0,0 +1,235 @@
+// Draws observations that have numbers for values as charts in the
+// terminal, using box drawing characters, or plain ASCII for terminals
+// that can't show them.
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const ASCII_SPARKS: [char; 8] = ['_', '.', '-', '~', '=', '+', '*', '#'];
+
+struct Glyphs {
+    point: char,
+    line: char,
+    axis: char,
+    tick: char,
+    corner: char,
+    floor: char,
+}
+
+const UNICODE: Glyphs = Glyphs {
+    point: '●',
+    line: '│',
+    axis: '│',
+    tick: '┤',
+    corner: '└',
+    floor: '─',
+};
+
+const ASCII: Glyphs = Glyphs {
+    point: '*',
+    line: '|',
+    axis: '|',
+    tick: '+',
+    corner: '+',
+    floor: '-',
+};
+
+// Reads the number at the start of an observation's value, so that values
+// like "12 bugs", "1,200" or "85%" can be charted. Returns None if the value
+// doesn't start with a number.
+pub fn parse_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let end = value
+        .char_indices()
+        .find(|(ix, c)| {
+            !(c.is_ascii_digit()
+                || *c == '.'
+                || *c == ','
+                || (*ix == 0 && (*c == '-' || *c == '+')))
+        })
+        .map_or(value.len(), |(ix, _)| ix);
+    value[..end].replace(',', "").parse().ok()
+}
+
+// Formats n without a fractional part if it doesn't have one, and to at
+// most two decimal places if it does.
+pub fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 {
+        return format!("{}", n);
+    }
+    let s = format!("{:.2}", n);
+    s.trim_end_matches('0').to_string()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub average: f64,
+}
+
+pub fn stats(values: &[f64]) -> Option<Stats> {
+    if values.is_empty() {
+        return None;
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let average = values.iter().sum::<f64>() / values.len() as f64;
+
+    Some(Stats { min, max, average })
+}
+
+// Shrinks values to at most width of them, by averaging runs of values
+// next to each other.
+pub fn downsample(values: &[f64], width: usize) -> Vec<f64> {
+    if width == 0 || values.len() <= width {
+        return values.to_vec();
+    }
+    (0..width)
+        .map(|col| {
+            let start = col * values.len() / width;
+            let end = (col + 1) * values.len() / width;
+            let run = &values[start..end];
+            run.iter().sum::<f64>() / run.len() as f64
+        })
+        .collect()
+}
+
+// Which of levels steps value falls on, between min and max.
+fn level(value: f64, min: f64, max: f64, levels: usize) -> usize {
+    if max <= min {
+        return 0;
+    }
+    let scaled = (value - min) / (max - min) * (levels - 1) as f64;
+    (scaled.round() as usize).min(levels - 1)
+}
+
+// Draws values as a single line of bars of different heights.
+pub fn sparkline(values: &[f64], ascii: bool) -> String {
+    let sparks = if ascii { &ASCII_SPARKS } else { &SPARKS };
+    let stats = match stats(values) {
+        Some(stats) => stats,
+        None => return String::new(),
+    };
+    values
+        .iter()
+        .map(|v| sparks[level(*v, stats.min, stats.max, sparks.len())])
+        .collect()
+}
+
+// Draws values as a line chart height rows tall, one column per value,
+// with the scale down the left hand side. first and last label the two
+// ends of the bottom axis.
+pub fn line_chart(
+    values: &[f64],
+    height: usize,
+    ascii: bool,
+    first: &str,
+    last: &str,
+) -> Vec<String> {
+    let glyphs = if ascii { &ASCII } else { &UNICODE };
+    let stats = match stats(values) {
+        Some(stats) => stats,
+        None => return vec![],
+    };
+    let height = height.max(2);
+
+    let mut grid = vec![vec![' '; values.len()]; height];
+    let mut previous: Option<usize> = None;
+    for (col, value) in values.iter().enumerate() {
+        let row = level(*value, stats.min, stats.max, height);
+        // Join each point to the one before it, so the line is unbroken.
+        if let Some(previous) = previous {
+            let (low, high) = (row.min(previous), row.max(previous));
+            for line in grid[low..high].iter_mut().skip(1) {
+                line[col] = glyphs.line;
+            }
+        }
+        grid[row][col] = glyphs.point;
+        previous = Some(row);
+    }
+
+    // The top, bottom and middle rows are labelled with their values.
+    let label = |row: usize| -> Option<String> {
+        if row == 0 || row == height - 1 || (height >= 5 && row == (height - 1) / 2) {
+            let value = stats.min + (stats.max - stats.min) * row as f64 / (height - 1) as f64;
+            Some(format_number(value))
+        } else {
+            None
+        }
+    };
+    let label_width = (0..height)
+        .filter_map(label)
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut lines = vec![];
+    for row in (0..height).rev() {
+        let (text, axis) = match label(row) {
+            Some(text) => (text, glyphs.tick),
+            None => (String::new(), glyphs.axis),
+        };
+        let points: String = grid[row].iter().collect();
+        lines.push(
+            format!("{:>width$} {}{}", text, axis, points, width = label_width)
+                .trim_end()
+                .to_string(),
+        );
+    }
+    let floor = glyphs.floor.to_string().repeat(values.len());
+    lines.push(format!(
+        "{:>width$} {}{}",
+        "",
+        glyphs.corner,
+        floor,
+        width = label_width
+    ));
+
+    // The dates go under the ends of the line, if there's room for both.
+    let indent = " ".repeat(label_width + 2);
+    let gap = values
+        .len()
+        .saturating_sub(first.chars().count() + last.chars().count());
+    if first == last {
+        lines.push(format!("{}{}", indent, first));
+    } else if gap > 0 {
+        lines.push(format!("{}{}{}{}", indent, first, " ".repeat(gap), last));
+    } else {
+        lines.push(format!("{}{} to {}", indent, first, last));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(Some(12.0), parse_number("12 bugs"));
+        assert_eq!(Some(1200.0), parse_number("1,200"));
+        assert_eq!(Some(85.5), parse_number("85.5%"));
+        assert_eq!(Some(-3.0), parse_number(" -3"));
+        assert_eq!(None, parse_number("sunny"));
+        assert_eq!(None, parse_number(""));
+        assert_eq!("7.5", format_number(7.5));
+        assert_eq!("7.33", format_number(22.0 / 3.0));
+        assert_eq!("12", format_number(12.0));
+    }
+
+    #[test]
+    fn test_charts() {
+        let values = [3.0, 5.0, 9.0, 7.0];
+        assert_eq!("▁▃█▆", sparkline(&values, false));
+        assert_eq!("_-#+", sparkline(&values, true));
+        assert_eq!(
+            vec!["9 +  *", "  |  |*", "  | *", "3 +*", "  +----", "   10-01",],
+            line_chart(&values, 4, true, "10-01", "10-01")
+        );
+        assert_eq!(
+            vec!["2 ┤", "2 ┤●●", "  └──", "   10-01"],
+            line_chart(&[2.0, 2.0], 2, false, "10-01", "10-01")
+        );
+        assert_eq!(vec![2.0, 5.0], downsample(&[1.0, 3.0, 4.0, 6.0], 2));
+    }
+}
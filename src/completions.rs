@@ -0,0 +1,260 @@
+use crate::entry::{Entry, Task};
+
+// Shell completion scripts are generated by clap from the command line
+// definition, which only knows about fixed values. Each script is extended
+// here so that task indexes, observation names and entry labels are filled
+// in by running the hidden coach __complete command, which prints one
+// candidate per line, optionally followed by a tab and a description.
+
+// Observation names are collected from this many of the latest entries.
+pub const RECENT_ENTRIES: usize = 30;
+
+pub const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+// Options that take a value, so the word after one isn't a subcommand.
+const VALUE_OPTIONS: &str = "--config|--journal-dir|--entry|-f|--timezone|--day-starts|--date|-d|--task-order|--output|--key-file";
+
+// Options that change which journal or entry coach __complete looks at, so
+// they're passed along to it from the command line being completed.
+const PASSED_OPTIONS: &str =
+    "--config|--journal-dir|--entry|-f|--timezone|--day-starts|--date|-d|--key-file";
+
+const TASK_COMMANDS: [&str; 7] = ["todo", "working", "done", "cancel", "edit", "rm", "mv"];
+
+// Each task by index, with the task as coach task lists it.
+pub fn task_candidates(tasks: &[Task]) -> Vec<String> {
+    tasks
+        .iter()
+        .enumerate()
+        .map(|(ix, task)| format!("{}\t{}", ix + 1, task))
+        .collect()
+}
+
+// The names of the observations in entries, latest entries first, without
+// repeats.
+pub fn observation_names(entries: &[Entry]) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for entry in entries.iter().rev() {
+        for observation in &entry.observations {
+            let name = observation.name.to_string();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names
+}
+
+fn bash_script() -> String {
+    format!(
+        r#"
+_coach_dynamic() {{
+    local cur prev i skip=0 candidates candidate quoted
+    local -a cmd=() opts=()
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        if [[ ${{skip}} -eq 1 ]]; then
+            skip=0
+            continue
+        fi
+        case "${{COMP_WORDS[i]}}" in
+            {passed})
+                if ((i + 1 < COMP_CWORD)); then
+                    opts+=("${{COMP_WORDS[i]}}" "${{COMP_WORDS[i+1]/#\~/$HOME}}")
+                fi
+                skip=1
+                ;;
+            {options}) skip=1 ;;
+            -*) ;;
+            *) cmd+=("${{COMP_WORDS[i]}}") ;;
+        esac
+    done
+
+    case "${{prev}}" in
+        --entry|-f|--date|-d) candidates="$(coach "${{opts[@]}}" __complete entries 2>/dev/null)" ;;
+        --history|--rm) candidates="$(coach "${{opts[@]}}" __complete observations 2>/dev/null)" ;;
+        *)
+            case "${{cmd[0]}} ${{cmd[1]}}:${{#cmd[@]}}" in
+                {tasks}) candidates="$(coach "${{opts[@]}}" __complete tasks 2>/dev/null | cut -f1)" ;;
+                "observe :1") candidates="$(coach "${{opts[@]}}" __complete observations 2>/dev/null)" ;;
+                *)
+                    _coach "$@"
+                    return
+                    ;;
+            esac
+            ;;
+    esac
+
+    # One candidate per line, which may have spaces in it, so compgen -W
+    # can't be used to split them.
+    COMPREPLY=()
+    while IFS= read -r candidate; do
+        printf -v quoted '%q' "${{candidate}}"
+        if [[ -n ${{candidate}} && ${{quoted}} == "${{cur}}"* ]]; then
+            COMPREPLY+=("${{quoted}}")
+        fi
+    done <<< "${{candidates}}"
+}}
+
+complete -F _coach_dynamic -o bashdefault -o default coach
+"#,
+        passed = PASSED_OPTIONS,
+        options = VALUE_OPTIONS,
+        tasks = TASK_COMMANDS
+            .iter()
+            .map(|c| format!("\"task {}:2\"", c))
+            .collect::<Vec<_>>()
+            .join("|"),
+    )
+}
+
+fn zsh_script() -> String {
+    format!(
+        r#"
+_coach() {{
+    local i skip=0
+    local -a cmd opts candidates
+    for ((i = 2; i < CURRENT; i++)); do
+        if (( skip )); then
+            skip=0
+            continue
+        fi
+        case $words[i] in
+            {passed})
+                if (( i + 1 < CURRENT )); then
+                    opts+=($words[i] ${{words[i+1]/#\~/$HOME}})
+                fi
+                skip=1
+                ;;
+            {options}) skip=1 ;;
+            -*) ;;
+            *) cmd+=($words[i]) ;;
+        esac
+    done
+
+    case $words[CURRENT-1] in
+        --entry|-f|--date|-d)
+            candidates=(${{(f)"$(coach $opts __complete entries 2>/dev/null)"}})
+            compadd -a candidates
+            return
+            ;;
+        --history|--rm)
+            candidates=(${{(f)"$(coach $opts __complete observations 2>/dev/null)"}})
+            compadd -a candidates
+            return
+            ;;
+    esac
+    if [[ $#cmd -eq 2 && $cmd[1] == task && $cmd[2] == ({tasks}) ]]; then
+        candidates=(${{(f)"$(coach $opts __complete tasks 2>/dev/null | tr '\t' :)"}})
+        _describe -V -t tasks task candidates
+    elif [[ $#cmd -eq 1 && $cmd[1] == observe ]]; then
+        candidates=(${{(f)"$(coach $opts __complete observations 2>/dev/null)"}})
+        compadd -a candidates
+        _coach_static "$@"
+    else
+        _coach_static "$@"
+    fi
+}}
+
+_coach "$@""#,
+        passed = PASSED_OPTIONS,
+        options = VALUE_OPTIONS,
+        tasks = TASK_COMMANDS.join("|"),
+    )
+}
+
+fn fish_script() -> String {
+    let mut script = format!(
+        r#"
+function __coach_complete
+    set -l opts
+    set -l words (commandline -opc)
+    set -e words[1]
+    while set -q words[2]
+        if contains -- $words[1] {passed}
+            set -a opts $words[1] (string replace -r '^~' $HOME -- $words[2])
+            set -e words[1]
+        end
+        set -e words[1]
+    end
+    coach $opts __complete $argv 2>/dev/null
+end
+"#,
+        passed = PASSED_OPTIONS.replace('|', " "),
+    );
+    script.push_str(&format!(
+        "complete -c coach -n \"__fish_seen_subcommand_from task; and __fish_seen_subcommand_from {}\" -f -a \"(__coach_complete tasks)\"\n",
+        TASK_COMMANDS.join(" ")
+    ));
+    script.push_str("complete -c coach -n \"__fish_seen_subcommand_from observe\" -f -a \"(__coach_complete observations)\"\n");
+    script.push_str("complete -c coach -n \"__fish_seen_subcommand_from observe\" -l history -r -f -a \"(__coach_complete observations)\"\n");
+    script.push_str("complete -c coach -n \"__fish_seen_subcommand_from observe\" -l rm -r -f -a \"(__coach_complete observations)\"\n");
+    script.push_str("complete -c coach -s f -l entry -r -f -a \"(__coach_complete entries)\"\n");
+    script.push_str("complete -c coach -s d -l date -r -f -a \"(__coach_complete entries)\"\n");
+
+    script
+}
+
+// Adds dynamic completion to the script clap generated for shell.
+pub fn add_dynamic(shell: &str, generated: &str) -> String {
+    match shell {
+        "bash" => format!("{}{}", generated, bash_script()),
+        // The generated _coach function ends up being called for every
+        // completion, so it's renamed for the dynamic one to fall back on.
+        "zsh" => {
+            let generated = generated.trim_end();
+            let generated = generated.strip_suffix("_coach \"$@\"").unwrap_or(generated);
+            let generated = generated.replacen("\n_coach() {", "\n_coach_static() {", 1);
+            format!("{}{}\n", generated, zsh_script())
+        }
+        "fish" => format!("{}{}", generated, fish_script()),
+        _ => generated.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+
+    #[test]
+    fn test_candidates() {
+        let older = entry::parse("#coach\n2021-10-30\nmood: ok\nweather: rainy\n").unwrap();
+        let newer = entry::parse(
+            "#coach\n2021-10-31\nsleep: 7\nmood: good\n\nTODO write README\nDONE lunch\n",
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["1\tTODO write README", "2\tDONE lunch"],
+            task_candidates(&newer.tasks)
+        );
+        assert_eq!(
+            vec!["sleep", "mood", "weather"],
+            observation_names(&[older, newer])
+        );
+    }
+
+    #[test]
+    fn test_add_dynamic_zsh() {
+        let generated = "#compdef coach\n\n_coach() {\n    _arguments\n}\n\n_coach \"$@\"";
+        let script = add_dynamic("zsh", generated);
+        assert!(script.contains("\n_coach_static() {\n    _arguments"));
+        assert!(script.contains("\n_coach() {\n"));
+        assert!(script.ends_with("\n_coach \"$@\"\n"));
+        assert_eq!(1, script.matches("_coach \"$@\"").count());
+    }
+
+    #[test]
+    fn test_options_are_passed_along() {
+        assert!(add_dynamic("bash", "").contains("coach \"${opts[@]}\" __complete tasks"));
+        assert!(add_dynamic("zsh", "").contains("coach $opts __complete tasks"));
+        assert!(add_dynamic("fish", "").contains("coach $opts __complete $argv"));
+        for shell in SHELLS {
+            let script = add_dynamic(shell, "");
+            assert!(script.contains("--journal-dir"));
+            assert!(!script.contains("coach __complete"));
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+
+// Settings can come from a TOML config file, by default
+// ~/.config/coach/config.toml, from environment variables, or from command
+// line flags. Flags win over the environment, which wins over the file.
+// The file looks like:
+//
+//   journal_dir = "~/journal"
+//   editor = "nano"
+//   timezone = "America/Los_Angeles"
+//   day_starts = "04:00"
+//   task_order = "manual"
+//   max_entry_size = 16384
+//
+//   [today]
+//   from_yesterday = true
+//   warn_stale = 7
+//
+// This module only finds each setting and checks its type, or that it's one
+// of the few values allowed. Checking that the value makes sense otherwise,
+// like that a time zone exists, is up to whoever uses it, so that it can be
+// checked the same way wherever it came from.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Text,
+    // A whole number from 0 up to and including the given maximum.
+    Integer(u64),
+    Boolean,
+    // One of the given words.
+    Choice(&'static [&'static str]),
+}
+
+struct Key {
+    name: &'static str,
+    env: &'static str,
+    kind: Kind,
+    default: Option<&'static str>,
+}
+
+// The values task_order can have.
+pub const TASK_ORDERS: [&str; 3] = ["sorted", "insertion", "manual"];
+
+// The largest max_entry_size that can be set, 64M.
+pub const MAX_ENTRY_SIZE_LIMIT: u64 = 64 * 1024 * 1024;
+
+const KEYS: [Key; 8] = [
+    Key {
+        name: "journal_dir",
+        env: "COACH_JOURNAL_DIR",
+        kind: Kind::Text,
+        default: Some("."),
+    },
+    Key {
+        name: "editor",
+        env: "COACH_EDITOR",
+        kind: Kind::Text,
+        default: Some("vi"),
+    },
+    Key {
+        name: "timezone",
+        env: "COACH_TIMEZONE",
+        kind: Kind::Text,
+        default: None,
+    },
+    Key {
+        name: "day_starts",
+        env: "COACH_DAY_STARTS",
+        kind: Kind::Text,
+        default: Some("00:00"),
+    },
+    Key {
+        name: "task_order",
+        env: "COACH_TASK_ORDER",
+        kind: Kind::Choice(&TASK_ORDERS),
+        default: Some("sorted"),
+    },
+    Key {
+        name: "max_entry_size",
+        env: "COACH_MAX_ENTRY_SIZE",
+        // A typical entry made by hand right now is around 1-2K. Entries
+        // are read whole, so the limit itself is kept well short of what
+        // could run out of memory.
+        kind: Kind::Integer(MAX_ENTRY_SIZE_LIMIT),
+        default: Some("8192"),
+    },
+    Key {
+        name: "today.from_yesterday",
+        env: "COACH_TODAY_FROM_YESTERDAY",
+        kind: Kind::Boolean,
+        default: Some("false"),
+    },
+    Key {
+        name: "today.warn_stale",
+        env: "COACH_TODAY_WARN_STALE",
+        kind: Kind::Integer(i64::MAX as u64),
+        // 0 turns the warning off.
+        default: Some("0"),
+    },
+];
+
+// The editor falls back to $EDITOR when it isn't set anywhere else. This
+// comes after the config file, since most people have EDITOR set anyway.
+const EDITOR_FALLBACK_ENV: &str = "EDITOR";
+
+// Where the setting's value came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Default,
+    File(String),
+    Env(&'static str),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(filename) => write!(f, "config file {}", filename),
+            Source::Env(name) => write!(f, "environment variable {}", name),
+            Source::Flag(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Setting {
+    pub name: &'static str,
+    pub value: Option<String>,
+    pub source: Source,
+}
+
+pub struct Config {
+    pub filename: Option<String>,
+    settings: Vec<Setting>,
+}
+
+// A flag given on the command line, for the setting named name.
+pub struct Flag<'a> {
+    pub name: &'static str,
+    pub flag: &'static str,
+    pub value: Option<&'a str>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+// $XDG_CONFIG_HOME/coach/config.toml, or ~/.config/coach/config.toml.
+pub fn default_filename() -> Option<String> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Path::new(&dir).to_path_buf(),
+        _ => Path::new(&env::var_os("HOME")?).join(".config"),
+    };
+    let path = config_dir.join("coach").join("config.toml");
+    path.to_str().map(|s| s.to_string())
+}
+
+// Replaces a leading ~/ in path with the home directory.
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest).to_str().unwrap().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+fn check_kind(key: &Key, value: &str, source: &Source) -> Result<(), io::Error> {
+    let ok = match key.kind {
+        Kind::Text => true,
+        Kind::Integer(max) => value.parse::<u64>().is_ok_and(|n| n <= max),
+        Kind::Boolean => value == "true" || value == "false",
+        Kind::Choice(choices) => choices.contains(&value),
+    };
+    if !ok {
+        let expected = match key.kind {
+            Kind::Text => String::from("text"),
+            Kind::Integer(max) => format!("a number from 0 to {}", max),
+            Kind::Boolean => String::from("true or false"),
+            Kind::Choice(choices) => format!("one of {}", choices.join(", ")),
+        };
+        return Err(invalid(format!(
+            "{} from {} must be {}, not {:?}",
+            key.name, source, expected, value
+        )));
+    }
+
+    Ok(())
+}
+
+// Flattens the file's tables into names like today.from_yesterday.
+fn file_values(filename: &str, contents: &str) -> Result<BTreeMap<String, String>, io::Error> {
+    let table: toml::Table = contents
+        .parse()
+        .map_err(|e| invalid(format!("couldn't read {}: {}", filename, e)))?;
+
+    let mut values = BTreeMap::new();
+    let mut pending: Vec<(String, toml::Value)> = table.into_iter().collect();
+    while let Some((name, value)) = pending.pop() {
+        let value = match value {
+            toml::Value::Table(table) => {
+                for (child, value) in table {
+                    pending.push((format!("{}.{}", name, child), value));
+                }
+                continue;
+            }
+            toml::Value::String(s) => s,
+            toml::Value::Integer(i) => i.to_string(),
+            toml::Value::Boolean(b) => b.to_string(),
+            other => other.to_string(),
+        };
+        if !KEYS.iter().any(|key| key.name == name) {
+            return Err(invalid(format!("unknown setting {} in {}", name, filename)));
+        }
+        values.insert(name, value);
+    }
+
+    Ok(values)
+}
+
+// Reads the config file, or the default config file if filename is None.
+// It's fine for the default file not to exist, but not one that was asked
+// for by name.
+pub fn load(filename: Option<&str>, flags: &[Flag]) -> Result<Config, io::Error> {
+    let (filename, required) = match filename {
+        Some(filename) => (Some(filename.to_string()), true),
+        None => (default_filename(), false),
+    };
+
+    let contents = match &filename {
+        Some(filename) => match fs::read_to_string(filename) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => None,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("couldn't read {}: {}", filename, e),
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let file = match (&filename, &contents) {
+        (Some(filename), Some(contents)) => Some((filename.as_str(), contents.as_str())),
+        _ => None,
+    };
+    let mut config = resolve(file, flags, |name| env::var(name).ok())?;
+    if contents.is_some() {
+        config.filename = filename;
+    }
+
+    Ok(config)
+}
+
+fn resolve<F>(file: Option<(&str, &str)>, flags: &[Flag], env: F) -> Result<Config, io::Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let from_file = match file {
+        Some((filename, contents)) => file_values(filename, contents)?,
+        None => BTreeMap::new(),
+    };
+
+    let mut settings = vec![];
+    for key in KEYS.iter() {
+        let flag = flags.iter().find(|f| f.name == key.name);
+        let (value, source) = if let Some(Flag {
+            flag,
+            value: Some(value),
+            ..
+        }) = flag
+        {
+            (Some(value.to_string()), Source::Flag(flag))
+        } else if let Some(value) = env(key.env) {
+            (Some(value), Source::Env(key.env))
+        } else if let Some(value) = from_file.get(key.name) {
+            (
+                Some(value.clone()),
+                Source::File(file.unwrap().0.to_string()),
+            )
+        } else if let Some(value) = env(EDITOR_FALLBACK_ENV).filter(|_| key.name == "editor") {
+            (Some(value), Source::Env(EDITOR_FALLBACK_ENV))
+        } else {
+            (key.default.map(|d| d.to_string()), Source::Default)
+        };
+
+        if let Some(value) = &value {
+            check_kind(key, value, &source)?;
+        }
+        settings.push(Setting {
+            name: key.name,
+            value,
+            source,
+        });
+    }
+
+    Ok(Config {
+        filename: None,
+        settings,
+    })
+}
+
+impl Config {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.setting(name).value.as_deref()
+    }
+
+    // Like get, for settings that always have a value.
+    pub fn value(&self, name: &str) -> &str {
+        self.get(name).unwrap()
+    }
+
+    pub fn setting(&self, name: &str) -> &Setting {
+        self.settings
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("no setting named {}", name))
+    }
+
+    pub fn settings(&self) -> &[Setting] {
+        &self.settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "
+journal_dir = \"~/journal\"
+max_entry_size = 16384
+
+[today]
+from_yesterday = true
+";
+
+    #[test]
+    fn test_precedence() {
+        let flags = [Flag {
+            name: "timezone",
+            flag: "--timezone",
+            value: Some("UTC"),
+        }];
+        let env = |name: &str| match name {
+            "COACH_TIMEZONE" => Some(String::from("-08:00")),
+            "COACH_MAX_ENTRY_SIZE" => Some(String::from("4096")),
+            "EDITOR" => Some(String::from("nano")),
+            _ => None,
+        };
+        let config = resolve(Some(("config.toml", FILE)), &flags, env).unwrap();
+
+        assert_eq!(Some("UTC"), config.get("timezone"));
+        assert_eq!(
+            Source::Flag("--timezone"),
+            config.setting("timezone").source
+        );
+        assert_eq!("4096", config.value("max_entry_size"));
+        assert_eq!("~/journal", config.value("journal_dir"));
+        assert_eq!(
+            Source::File(String::from("config.toml")),
+            config.setting("journal_dir").source
+        );
+        assert_eq!("true", config.value("today.from_yesterday"));
+        assert_eq!("nano", config.value("editor"));
+        assert_eq!("sorted", config.value("task_order"));
+        assert_eq!(Source::Default, config.setting("task_order").source);
+    }
+
+    #[test]
+    fn test_bad_files() {
+        let no_env = |_: &str| None;
+        assert!(resolve(Some(("c", "jornal_dir = \".\"")), &[], no_env).is_err());
+        assert!(resolve(Some(("c", "max_entry_size = \"big\"")), &[], no_env).is_err());
+        assert!(resolve(
+            Some(("c", "max_entry_size = 18446744073709551615")),
+            &[],
+            no_env
+        )
+        .is_err());
+        assert!(resolve(Some(("c", "max_entry_size = 67108865")), &[], no_env).is_err());
+        assert!(resolve(Some(("c", "max_entry_size = 67108864")), &[], no_env).is_ok());
+        assert!(resolve(Some(("c", "journal_dir = ")), &[], no_env).is_err());
+        assert!(resolve(Some(("c", "task_order = \"random\"")), &[], no_env).is_err());
+        assert!(resolve(Some(("c", "task_order = \"insertion\"")), &[], no_env).is_ok());
+        assert!(resolve(None, &[], no_env).is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use zeroize::Zeroizing;
+
+// An encrypted entry file starts with MAGIC, followed by a random salt for
+// deriving the key from the passphrase, a random nonce, and finally the
+// entry text encrypted with ChaCha20-Poly1305.
+const MAGIC: &[u8] = b"#coach-encrypted\n";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+// How many more bytes an encrypted file takes than the text it holds.
+pub const OVERHEAD: usize = MAGIC.len() + SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH;
+
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+// A Keyring holds on to the journal passphrase, so that it is only read or
+// prompted for once, and only if an encrypted entry is actually used.
+pub struct Keyring {
+    key_file: Option<String>,
+    encrypt_new: bool,
+    passphrase: RefCell<Option<Zeroizing<String>>>,
+}
+
+impl Keyring {
+    // If key_file is None, the passphrase is read from the terminal.
+    // If encrypt_new is true, newly created entries will be encrypted.
+    pub fn new(key_file: Option<String>, encrypt_new: bool) -> Keyring {
+        Keyring {
+            key_file,
+            encrypt_new,
+            passphrase: RefCell::new(None),
+        }
+    }
+
+    pub fn encrypts_new_entries(&self) -> bool {
+        self.encrypt_new
+    }
+
+    // Whether the passphrase can be had without asking for it.
+    pub fn has_key_file(&self) -> bool {
+        self.key_file.is_some()
+    }
+
+    // Prompted passphrases are asked for twice when encrypting, so that a
+    // typo doesn't lock you out of your entry.
+    fn passphrase(&self, confirm: bool) -> Result<Zeroizing<String>, io::Error> {
+        if let Some(passphrase) = self.passphrase.borrow().as_ref() {
+            return Ok(passphrase.clone());
+        }
+
+        let passphrase = match &self.key_file {
+            Some(key_file) => {
+                let contents = Zeroizing::new(fs::read_to_string(key_file)?);
+                Zeroizing::new(contents.trim_end_matches(&['\r', '\n'][..]).to_string())
+            }
+            None => {
+                let passphrase = Zeroizing::new(rpassword::prompt_password("passphrase: ")?);
+                if confirm {
+                    let again = Zeroizing::new(rpassword::prompt_password("confirm passphrase: ")?);
+                    if passphrase != again {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidInput,
+                            "passphrases didn't match",
+                        ));
+                    }
+                }
+                passphrase
+            }
+        };
+
+        if passphrase.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "the passphrase can't be empty",
+            ));
+        }
+
+        *self.passphrase.borrow_mut() = Some(passphrase.clone());
+        Ok(passphrase)
+    }
+
+    fn cipher(&self, salt: &[u8], confirm: bool) -> Result<ChaCha20Poly1305, io::Error> {
+        let passphrase = self.passphrase(confirm)?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key[..])))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher = self.cipher(&salt, true)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| io::Error::other("couldn't encrypt entry"))?;
+
+        let mut ret = Vec::with_capacity(plaintext.len() + OVERHEAD);
+        ret.extend_from_slice(MAGIC);
+        ret.extend_from_slice(&salt);
+        ret.extend_from_slice(&nonce);
+        ret.extend_from_slice(&ciphertext);
+        Ok(ret)
+    }
+
+    pub fn decrypt(&self, contents: &[u8]) -> Result<Zeroizing<Vec<u8>>, io::Error> {
+        if !is_encrypted(contents) || contents.len() < OVERHEAD {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "this is not an encrypted coach file",
+            ));
+        }
+
+        let contents = &contents[MAGIC.len()..];
+        let (salt, contents) = contents.split_at(SALT_LENGTH);
+        let (nonce, ciphertext) = contents.split_at(NONCE_LENGTH);
+        let cipher = self.cipher(salt, false)?;
+        match cipher.decrypt(Nonce::from_slice(nonce), ciphertext) {
+            Ok(plaintext) => Ok(Zeroizing::new(plaintext)),
+            Err(_) => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "couldn't decrypt entry. The passphrase may be wrong, or the file may be damaged",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn keyring_with_passphrase(passphrase: &str) -> (tempfile::NamedTempFile, Keyring) {
+        let mut key_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(key_file, "{}", passphrase).unwrap();
+        let path = key_file.path().to_str().unwrap().to_string();
+        (key_file, Keyring::new(Some(path), true))
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (_f, keyring) = keyring_with_passphrase("correct horse");
+        let encrypted = keyring.encrypt(b"#coach\nTest\n\n").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(
+            b"#coach\nTest\n\n".to_vec(),
+            *keyring.decrypt(&encrypted).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let (_f, keyring) = keyring_with_passphrase("correct horse");
+        let encrypted = keyring.encrypt(b"#coach\nTest\n\n").unwrap();
+        let (_g, wrong) = keyring_with_passphrase("battery staple");
+        assert!(wrong.decrypt(&encrypted).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use time::{Date, Duration, OffsetDateTime, Time, UtcOffset, Weekday};
+
+use crate::journal::DATE_FORMAT;
+use crate::zoneinfo;
+
+#[derive(Debug, PartialEq)]
+pub enum Timezone {
+    // Whatever the system says the local time zone is.
+    Local,
+    Fixed(UtcOffset),
+    // A name from the tz database, like America/Los_Angeles.
+    Named(String),
+}
+
+const WEEKDAYS: [(Weekday, &str, &str); 7] = [
+    (Weekday::Monday, "monday", "mon"),
+    (Weekday::Tuesday, "tuesday", "tue"),
+    (Weekday::Wednesday, "wednesday", "wed"),
+    (Weekday::Thursday, "thursday", "thu"),
+    (Weekday::Friday, "friday", "fri"),
+    (Weekday::Saturday, "saturday", "sat"),
+    (Weekday::Sunday, "sunday", "sun"),
+];
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    WEEKDAYS
+        .iter()
+        .find(|(_, long, short)| s == *long || s == *short)
+        .map(|(day, _, _)| *day)
+}
+
+// How many days back from "from" the most recent day is, counting
+// "from" itself as zero days back.
+fn days_since(from: Date, day: Weekday) -> i64 {
+    let from_ix = from.weekday().number_days_from_monday() as i64;
+    let day_ix = day.number_days_from_monday() as i64;
+    (from_ix - day_ix).rem_euclid(7)
+}
+
+// How many days forward from "from" the next day is, counting "from"
+// itself as zero days forward.
+fn days_until(from: Date, day: Weekday) -> i64 {
+    let from_ix = from.weekday().number_days_from_monday() as i64;
+    let day_ix = day.number_days_from_monday() as i64;
+    (day_ix - from_ix).rem_euclid(7)
+}
+
+// Parses a date relative to today. Accepts
+//
+//   - dates like 2021-10-31,
+//   - today, yesterday and tomorrow,
+//   - a number of days from today, like -3 or +1,
+//   - a weekday like friday or fri, meaning the most recent one, which
+//     may be today,
+//   - last friday, meaning the most recent one before today, and
+//   - next friday, meaning the first one after today.
+//
+// Returns None if s isn't any of those.
+pub fn parse_date(s: &str, today: Date) -> Option<Date> {
+    if let Ok(d) = Date::parse(s, &DATE_FORMAT) {
+        return Some(d);
+    }
+
+    let s = s.trim().to_lowercase();
+    let offset = match s.as_str() {
+        "today" => 0,
+        "yesterday" => -1,
+        "tomorrow" => 1,
+        x if x.starts_with('-') || x.starts_with('+') => x.parse::<i64>().ok()?,
+        x => {
+            let words: Vec<&str> = x.split_whitespace().collect();
+            match words[..] {
+                [day] => -days_since(today, parse_weekday(day)?),
+                ["last", day] => -1 - days_since(today.previous_day()?, parse_weekday(day)?),
+                ["next", day] => 1 + days_until(today.next_day()?, parse_weekday(day)?),
+                _ => return None,
+            }
+        }
+    };
+
+    // Duration::days panics rather than overflowing, so offsets too large
+    // to be a date are turned away first.
+    today.checked_add(Duration::seconds(offset.checked_mul(86_400)?))
+}
+
+fn zoneinfo_exists(name: &str) -> bool {
+    zoneinfo::path(name).is_some_and(|path| path.is_file())
+}
+
+fn parse_offset(s: &str) -> Option<UtcOffset> {
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = match s[1..].split_once(':') {
+        Some((h, m)) => (h, m),
+        None => (&s[1..], "0"),
+    };
+    let hours: i8 = hours.parse().ok()?;
+    let minutes: i8 = minutes.parse().ok()?;
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+// Parses "local", "UTC", an offset from UTC like +05:30 or -08:00, or
+// the name of a time zone installed on this system, like Europe/Paris.
+pub fn parse_timezone(s: &str) -> Option<Timezone> {
+    match s {
+        "local" => Some(Timezone::Local),
+        "UTC" | "utc" | "Z" => Some(Timezone::Fixed(UtcOffset::UTC)),
+        x if x.starts_with('+') || x.starts_with('-') => parse_offset(x).map(Timezone::Fixed),
+        x if zoneinfo_exists(x) => Some(Timezone::Named(x.to_string())),
+        _ => None,
+    }
+}
+
+// Parses a time of day like 04:00 or 4:30.
+pub fn parse_time_of_day(s: &str) -> Option<Time> {
+    let (hours, minutes) = s.split_once(':')?;
+    Time::from_hms(hours.parse().ok()?, minutes.parse().ok()?, 0).ok()
+}
+
+// Returns the date of the entry that a moment belongs in, if days
+// start at day_starts rather than at midnight.
+pub fn entry_date(when: OffsetDateTime, day_starts: Time) -> Date {
+    (when - (day_starts - Time::MIDNIGHT)).date()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    // A Wednesday
+    const TODAY: Date = date!(2021 - 11 - 03);
+
+    #[test]
+    fn test_absolute_dates() {
+        assert_eq!(Some(date!(2021 - 10 - 31)), parse_date("2021-10-31", TODAY));
+        assert_eq!(None, parse_date("2021-13-31", TODAY));
+        assert_eq!(None, parse_date("someday", TODAY));
+    }
+
+    #[test]
+    fn test_relative_days() {
+        assert_eq!(Some(TODAY), parse_date("today", TODAY));
+        assert_eq!(Some(date!(2021 - 11 - 02)), parse_date("yesterday", TODAY));
+        assert_eq!(Some(date!(2021 - 10 - 31)), parse_date("-3", TODAY));
+        assert_eq!(Some(date!(2021 - 11 - 04)), parse_date("+1", TODAY));
+        assert_eq!(None, parse_date("+999999999999999999", TODAY));
+        assert_eq!(None, parse_date("-99999999", TODAY));
+    }
+
+    #[test]
+    fn test_weekdays() {
+        assert_eq!(Some(date!(2021 - 11 - 01)), parse_date("monday", TODAY));
+        assert_eq!(Some(TODAY), parse_date("wed", TODAY));
+        assert_eq!(Some(date!(2021 - 10 - 29)), parse_date("Friday", TODAY));
+        assert_eq!(
+            Some(date!(2021 - 10 - 27)),
+            parse_date("last wednesday", TODAY)
+        );
+        assert_eq!(
+            Some(date!(2021 - 10 - 29)),
+            parse_date("last friday", TODAY)
+        );
+        assert_eq!(
+            Some(date!(2021 - 11 - 10)),
+            parse_date("next wednesday", TODAY)
+        );
+        assert_eq!(Some(date!(2021 - 11 - 05)), parse_date("next fri", TODAY));
+        assert_eq!(None, parse_date("last week", TODAY));
+    }
+
+    #[test]
+    fn test_timezones() {
+        use time::macros::offset;
+        assert_eq!(Some(Timezone::Local), parse_timezone("local"));
+        assert_eq!(Some(Timezone::Fixed(offset!(UTC))), parse_timezone("UTC"));
+        assert_eq!(
+            Some(Timezone::Fixed(offset!(+5:30))),
+            parse_timezone("+05:30")
+        );
+        assert_eq!(Some(Timezone::Fixed(offset!(-8))), parse_timezone("-08"));
+        assert_eq!(None, parse_timezone("Not/A_Zone"));
+        assert_eq!(None, parse_timezone("../../etc/passwd"));
+    }
+
+    #[test]
+    fn test_entry_date_with_late_rollover() {
+        use time::macros::{datetime, time};
+        let late = datetime!(2021-11-03 01:30 -8);
+        assert_eq!(date!(2021 - 11 - 02), entry_date(late, time!(04:00)));
+        assert_eq!(date!(2021 - 11 - 03), entry_date(late, time!(00:00)));
+        assert_eq!(Some(time!(04:00)), parse_time_of_day("4:00"));
+        assert_eq!(None, parse_time_of_day("25:00"));
+    }
+}
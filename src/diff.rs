@@ -0,0 +1,257 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+use crate::entry::{Entry, Task};
+use crate::search::{Section, SECTIONS};
+
+// A diff between two entries, made from what's in them rather than from
+// their lines, so that tasks moving around or being sorted don't show up
+// as changes. Tasks are matched up by their messages, and observations by
+// their names. Events and notes only ever get added to an entry, so only
+// new ones are listed.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Kind::Added => "added",
+            Kind::Removed => "removed",
+            Kind::Changed => "changed",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// What changed. For tasks, name is the task's message and from and to are
+// its states, like TODO. For observations, name is the observation's name
+// and from and to are its values. New events and notes are held entirely
+// in to.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub section: Section,
+    pub kind: Kind,
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+fn state(task: &Task) -> String {
+    task.state().to_uppercase()
+}
+
+// The values of each observation, by name, in the order the names first
+// turn up. An observation recorded more than once has all its values.
+fn observation_values(entry: &Entry) -> Vec<(String, String)> {
+    let mut values: Vec<(String, String)> = vec![];
+    for ob in &entry.observations {
+        let name = ob.name.to_string();
+        let value = ob.value.to_string();
+        match values.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => *values = format!("{}, {}", values, value),
+            None => values.push((name, value)),
+        }
+    }
+
+    values
+}
+
+// The changes that turn from into to, by section, in the order things are
+// in to, followed by anything removed from from.
+pub fn diff(from: &Entry, to: &Entry) -> Vec<Change> {
+    let mut changes = vec![];
+
+    let (old, new) = (observation_values(from), observation_values(to));
+    for (name, value) in &new {
+        match old.iter().find(|(n, _)| n == name) {
+            Some((_, old_value)) if old_value == value => {}
+            Some((_, old_value)) => changes.push(Change {
+                section: Section::Observations,
+                kind: Kind::Changed,
+                name: name.clone(),
+                from: Some(old_value.clone()),
+                to: Some(value.clone()),
+            }),
+            None => changes.push(Change {
+                section: Section::Observations,
+                kind: Kind::Added,
+                name: name.clone(),
+                from: None,
+                to: Some(value.clone()),
+            }),
+        }
+    }
+    for (name, value) in &old {
+        if !new.iter().any(|(n, _)| n == name) {
+            changes.push(Change {
+                section: Section::Observations,
+                kind: Kind::Removed,
+                name: name.clone(),
+                from: Some(value.clone()),
+                to: None,
+            });
+        }
+    }
+
+    for task in &to.tasks {
+        match from.tasks.iter().find(|t| t.text() == task.text()) {
+            Some(old_task) if old_task.state() == task.state() => {}
+            Some(old_task) => changes.push(Change {
+                section: Section::Tasks,
+                kind: Kind::Changed,
+                name: task.text().to_string(),
+                from: Some(state(old_task)),
+                to: Some(state(task)),
+            }),
+            None => changes.push(Change {
+                section: Section::Tasks,
+                kind: Kind::Added,
+                name: task.text().to_string(),
+                from: None,
+                to: Some(state(task)),
+            }),
+        }
+    }
+    for task in &from.tasks {
+        if !to.tasks.iter().any(|t| t.text() == task.text()) {
+            changes.push(Change {
+                section: Section::Tasks,
+                kind: Kind::Removed,
+                name: task.text().to_string(),
+                from: Some(state(task)),
+                to: None,
+            });
+        }
+    }
+
+    let old_events: Vec<String> = from.events.iter().map(|e| e.to_string()).collect();
+    for event in to.events.iter().map(|e| e.to_string()) {
+        if !old_events.contains(&event) {
+            changes.push(Change {
+                section: Section::Events,
+                kind: Kind::Added,
+                name: String::new(),
+                from: None,
+                to: Some(event),
+            });
+        }
+    }
+
+    let old_notes: Vec<String> = from.notes.iter().map(|n| n.to_string()).collect();
+    for note in to.notes.iter().map(|n| n.to_string()) {
+        if !old_notes.contains(&note) {
+            changes.push(Change {
+                section: Section::Notes,
+                kind: Kind::Added,
+                name: String::new(),
+                from: None,
+                to: Some(note.trim_end().to_string()),
+            });
+        }
+    }
+
+    changes
+}
+
+// Writes changes under a heading for each section that has any, marking
+// additions with +, removals with - and changes with ~.
+pub fn write<W: Write>(out: &mut W, changes: &[Change]) -> io::Result<()> {
+    let mut first = true;
+    for section in SECTIONS {
+        let in_section: Vec<&Change> = changes.iter().filter(|c| c.section == section).collect();
+        if in_section.is_empty() {
+            continue;
+        }
+        if !first {
+            writeln!(out)?;
+        }
+        first = false;
+
+        writeln!(out, "{}", section.name())?;
+        for change in in_section {
+            let from = change.from.as_deref().unwrap_or("");
+            let to = change.to.as_deref().unwrap_or("");
+            let line = match (section, change.kind) {
+                (Section::Tasks, Kind::Added) => format!("+ {} {}", to, change.name),
+                (Section::Tasks, Kind::Removed) => format!("- {} {}", from, change.name),
+                (Section::Tasks, Kind::Changed) => {
+                    format!("~ {}: {} -> {}", change.name, from, to)
+                }
+                (_, Kind::Added) if change.name.is_empty() => format!("+ {}", to),
+                (_, Kind::Added) => format!("+ {}: {}", change.name, to),
+                (_, Kind::Removed) => format!("- {}: {}", change.name, from),
+                (_, Kind::Changed) => format!("~ {}: {} -> {}", change.name, from, to),
+            };
+            // Notes can run over several lines, which are lined up under
+            // the first.
+            for (ix, text) in line.lines().enumerate() {
+                if ix == 0 {
+                    writeln!(out, "  {}", text)?;
+                } else {
+                    writeln!(out, "    {}", text)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+
+    #[test]
+    fn test_diff() {
+        let from = entry::parse(
+            "#coach\n2021-10-31\nmood: ok\nsteps: 1000\n\nTODO carve pumpkin\nDONE buy candy\nTODO rake\n\n* <2021-10-31 Sun 10:03> bought candy\n",
+        )
+        .unwrap();
+        let to = entry::parse(
+            "#coach\n2021-11-01\nmood: good\nweather: rainy\n\nTODO rake\nWORKING carve pumpkin\nTODO eat candy\n\n* <2021-10-31 Sun 10:03> bought candy\n* <2021-11-01 Mon 09:00> ate candy\n\nToo much candy.\nFar too much.\n\n",
+        )
+        .unwrap();
+        let changes = diff(&from, &to);
+        assert_eq!(
+            Change {
+                section: Section::Tasks,
+                kind: Kind::Changed,
+                name: String::from("carve pumpkin"),
+                from: Some(String::from("TODO")),
+                to: Some(String::from("WORKING")),
+            },
+            changes[3]
+        );
+
+        let mut out = vec![];
+        write(&mut out, &changes).unwrap();
+        assert_eq!(
+            "observations
+  ~ mood: ok -> good
+  + weather: rainy
+  - steps: 1000
+
+tasks
+  ~ carve pumpkin: TODO -> WORKING
+  + TODO eat candy
+  - DONE buy candy
+
+events
+  + <2021-11-01 Mon 09:00> ate candy
+
+notes
+  + Too much candy.
+    Far too much.
+",
+            String::from_utf8(out).unwrap()
+        );
+        assert!(diff(&to, &to).is_empty());
+    }
+}
@@ -1,31 +1,48 @@
-use std::env;
-use std::ffi::OsString;
-use std::fs::{File, OpenOptions};
-use std::io::{self, Read};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::process::Command;
 use tempfile::NamedTempFile;
+use zeroize::Zeroizing;
 
 // TODO might be nice to write a prompt to the file?
-pub fn edit_prompt() -> Result<String, io::Error> {
-    let tf = NamedTempFile::new().unwrap();
+pub fn edit_prompt(editor: &str) -> Result<Zeroizing<String>, io::Error> {
+    edit_text(editor, "")
+}
+
+// Opens text in the editor, and returns the edited text. The text is
+// kept in a private temporary file while it's being edited, which is
+// overwritten with zeros and removed afterwards. This is best effort:
+// editors may still keep their own backup or swap files.
+pub fn edit_text(editor: &str, text: &str) -> Result<Zeroizing<String>, io::Error> {
+    let mut tf = NamedTempFile::new()?;
+    tf.write_all(text.as_bytes())?;
+    tf.as_file().sync_all()?;
     let path = tf.into_temp_path();
-    launch_editor(path.to_str().unwrap())?;
-    let mut ret = String::new();
 
     // Can't use tf.reopen(), it (appears) that vi
     // on my mac doesn't actually edit the file, just
     // copies and renames.
-    let mut inf = File::open(path)?;
-    inf.read_to_string(&mut ret)?;
-    Ok(ret)
+    let edited = launch_editor(editor, path.to_str().unwrap()).and_then(|_| {
+        let mut ret = Zeroizing::new(String::new());
+        File::open(&path)?.read_to_string(&mut ret)?;
+        Ok(ret)
+    });
+
+    shred(path.to_str().unwrap())?;
+    path.close()?;
+    edited
 }
 
-// $EDITOR support is minimal - EDITOR isn't run through a shell,
-// so cool (and common!) tricks like EDITOR='vim -e' will break.
-pub fn launch_editor(filename: &str) -> Result<(), io::Error> {
-    let vi = OsString::from("vi");
-    let editor = env::var_os("EDITOR").unwrap_or(vi);
+fn shred(filename: &str) -> Result<(), io::Error> {
+    let length = fs::metadata(filename)?.len();
+    let mut f = OpenOptions::new().write(true).open(filename)?;
+    f.write_all(&vec![0u8; length as usize])?;
+    f.sync_all()
+}
 
+// Editor support is minimal - editor isn't run through a shell,
+// so cool (and common!) tricks like EDITOR='vim -e' will break.
+pub fn launch_editor(editor: &str, filename: &str) -> Result<(), io::Error> {
     let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
     let tty_in = tty.try_clone()?;
     let mut editor = Command::new(editor)
@@ -4,7 +4,7 @@ use std::fmt;
 use std::mem;
 use time::format_description::FormatItem;
 use time::macros::format_description;
-use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+use time::{OffsetDateTime, PrimitiveDateTime};
 
 // You should only construct a NoNewlines if you know for a fact
 // that the contained string has no newlines.
@@ -19,7 +19,7 @@ pub fn as_no_newlines(s: String) -> Option<NoNewlines> {
     }
 }
 
-impl<'a> fmt::Display for NoNewlines {
+impl fmt::Display for NoNewlines {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let NoNewlines(s) = self;
         write!(f, "{}", s)
@@ -52,7 +52,7 @@ impl<'a> Arbitrary<'a> for NoNewlines {
 pub struct ObservationName(String);
 
 pub fn as_observation_name(s: String) -> Option<ObservationName> {
-    if s.is_empty() || s.contains(|c| c == '\n' || c == ':') {
+    if s.is_empty() || s.contains(['\n', ':']) {
         return None;
     }
 
@@ -78,7 +78,7 @@ pub struct Observation {
     pub value: NoNewlines,
 }
 
-impl<'a> fmt::Display for Observation {
+impl fmt::Display for Observation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", &self.name, &self.value)
     }
@@ -94,10 +94,7 @@ pub enum Task {
 
 impl Task {
     pub fn is_incomplete(&self) -> bool {
-        match self {
-            Task::Todo(_) | Task::Working(_) => true,
-            _ => false,
-        }
+        matches!(self, Task::Todo(_) | Task::Working(_))
     }
 
     pub fn message(&mut self) -> &mut NoNewlines {
@@ -108,9 +105,25 @@ impl Task {
             Task::Cancelled(s) => s,
         }
     }
+
+    pub fn text(&self) -> &str {
+        match self {
+            Task::Todo(s) | Task::Done(s) | Task::Working(s) | Task::Cancelled(s) => &s.0,
+        }
+    }
+
+    // The task's state in lower case, like todo.
+    pub fn state(&self) -> &'static str {
+        match self {
+            Task::Todo(_) => "todo",
+            Task::Working(_) => "working",
+            Task::Done(_) => "done",
+            Task::Cancelled(_) => "cancelled",
+        }
+    }
 }
 
-impl<'a> fmt::Display for Task {
+impl fmt::Display for Task {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Task::Todo(s) => write!(f, "TODO {}", s),
@@ -121,15 +134,29 @@ impl<'a> fmt::Display for Task {
     }
 }
 
+// How the tasks in an entry are kept in order as they change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaskOrder {
+    // By state, then by message.
+    Sorted,
+    // In the order they were added.
+    Insertion,
+    // Wherever they were put with coach task mv.
+    Manual,
+}
+
+// Event times are the times of day written in the entry, in whatever time
+// zone was in use when they were written. Entries don't record the zone, so
+// times are kept without an offset rather than guessing one.
 #[derive(Debug, PartialEq)]
 pub enum Event {
     Moment {
-        when: OffsetDateTime,
+        when: PrimitiveDateTime,
         text: NoNewlines,
     },
     Interval {
-        begin: OffsetDateTime,
-        end: OffsetDateTime,
+        begin: PrimitiveDateTime,
+        end: PrimitiveDateTime,
         text: NoNewlines,
     },
 }
@@ -138,7 +165,7 @@ const TIMESTAMP_FORMAT: &[FormatItem<'static>] = format_description!(
     "[year]-[month repr:numerical]-[day] [weekday repr:short] [hour repr:24]:[minute]"
 );
 
-impl<'a> fmt::Display for Event {
+impl fmt::Display for Event {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Event::Moment { when, text } => {
@@ -159,8 +186,11 @@ impl<'a> Arbitrary<'a> for Event {
         let text = u.arbitrary::<NoNewlines>()?;
         let begin_stamp = u.int_in_range::<i64>(0..=2147483640)?;
         let end_stamp = u.int_in_range(begin_stamp..=2147483640)?;
-        let begin = OffsetDateTime::from_unix_timestamp(begin_stamp).unwrap();
-        let end = OffsetDateTime::from_unix_timestamp(end_stamp).unwrap();
+        let naive = |stamp| {
+            let utc = OffsetDateTime::from_unix_timestamp(stamp).unwrap();
+            PrimitiveDateTime::new(utc.date(), utc.time())
+        };
+        let (begin, end) = (naive(begin_stamp), naive(end_stamp));
         if u.arbitrary()? {
             Ok(Event::Moment { text, when: begin })
         } else {
@@ -198,7 +228,7 @@ pub fn as_note(s: String) -> Option<Note> {
     Some(Note(s))
 }
 
-impl<'a> fmt::Display for Note {
+impl fmt::Display for Note {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
     }
@@ -243,9 +273,58 @@ impl Entry {
         let new_task = updater(old_message);
         let _ = mem::replace(&mut self.tasks[ix], new_task);
     }
+
+    // Replaces the value of the observation named name, or adds it if there
+    // isn't one. If there was more than one, only the first is kept.
+    pub fn set_observation(&mut self, name: ObservationName, value: NoNewlines) {
+        match self.observations.iter().position(|ob| ob.name == name) {
+            Some(ix) => {
+                self.observations[ix].value = value;
+                let mut seen = 0;
+                self.observations.retain(|ob| {
+                    if ob.name == name {
+                        seen += 1;
+                    }
+                    ob.name != name || seen == 1
+                });
+            }
+            None => self.observations.push(Observation { name, value }),
+        }
+    }
+
+    // Removes every observation named name, and returns how many there were.
+    pub fn remove_observation(&mut self, name: &ObservationName) -> usize {
+        let before = self.observations.len();
+        self.observations.retain(|ob| ob.name != *name);
+        before - self.observations.len()
+    }
+
+    // Puts the tasks back in order after a change. Only Sorted actually
+    // moves anything. Otherwise tasks stay where they were put.
+    pub fn order_tasks(&mut self, order: TaskOrder) {
+        if order == TaskOrder::Sorted {
+            self.tasks.sort();
+        }
+    }
+
+    // Replaces the message of a task, keeping its state.
+    pub fn edit_task(&mut self, ix: usize, message: NoNewlines) {
+        let _ = mem::replace(self.tasks[ix].message(), message);
+    }
+
+    pub fn remove_task(&mut self, ix: usize) -> Task {
+        self.tasks.remove(ix)
+    }
+
+    // Moves a task so that it ends up at index to, shifting the tasks
+    // in between over by one.
+    pub fn move_task(&mut self, from: usize, to: usize) {
+        let task = self.tasks.remove(from);
+        self.tasks.insert(to, task);
+    }
 }
 
-impl<'a> Default for Entry {
+impl Default for Entry {
     fn default() -> Self {
         Entry {
             label: NoNewlines(String::from("PLACEHOLDER")),
@@ -257,7 +336,7 @@ impl<'a> Default for Entry {
     }
 }
 
-impl<'a> fmt::Display for Entry {
+impl fmt::Display for Entry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "#coach")?;
         writeln!(f, "{}", self.label)?;
@@ -522,7 +601,7 @@ fn consume_event(remaining: &str) -> ConsumeResult<'_, Event> {
     }
 }
 
-fn consume_timestamp(remaining: &str) -> ConsumeResult<'_, OffsetDateTime> {
+fn consume_timestamp(remaining: &str) -> ConsumeResult<'_, PrimitiveDateTime> {
     if !remaining.starts_with('<') {
         return ConsumeResult::Problem(ParseError::MissingTimestamp);
     }
@@ -535,7 +614,7 @@ fn consume_timestamp(remaining: &str) -> ConsumeResult<'_, OffsetDateTime> {
     };
 
     let found = match PrimitiveDateTime::parse(when_text.trim(), &TIMESTAMP_FORMAT) {
-        Ok(d) => d.assume_offset(UtcOffset::UTC),
+        Ok(d) => d,
         Err(_) => {
             return ConsumeResult::Problem(ParseError::MalformedTimestamp);
         }
@@ -646,11 +725,11 @@ CANCELLED teach the dog rust
             tasks: vec![],
             events: vec![
                 Event::Moment {
-                    when: datetime!(2021-10-31 21:00 UTC),
+                    when: datetime!(2021-10-31 21:00),
                     text: NoNewlines(String::from("working in the lab late one night")),
                 },
                 Event::Moment {
-                    when: datetime!(2021-10-31 22:10 UTC),
+                    when: datetime!(2021-10-31 22:10),
                     text: NoNewlines(String::from("my eyes beheld an eerie sight")),
                 },
             ],
@@ -745,11 +824,11 @@ it is multiline
         assert_eq!(
             vec![
                 Event::Moment {
-                    when: datetime!(2021-10-31 21:10:00 UTC),
+                    when: datetime!(2021-10-31 21:10:00),
                     text: NoNewlines(String::from("working in the lab late one night")),
                 },
                 Event::Moment {
-                    when: datetime!(2021-10-31 22:10:00 UTC),
+                    when: datetime!(2021-10-31 22:10:00),
                     text: NoNewlines(String::from("my eyes beheld an eerie sight")),
                 },
             ],
@@ -757,6 +836,12 @@ it is multiline
         )
     }
 
+    #[test]
+    fn test_event_times_roundtrip() {
+        let text = "#coach\nTest\n\n* <2021-10-31 Sun 23:30>--<2021-11-01 Mon 00:15> late\n\n";
+        assert_eq!(text, parse(text).unwrap().to_string());
+    }
+
     #[test]
     fn test_parse_notes() {
         let e = parse(MESSAGE).unwrap();
@@ -807,4 +892,84 @@ it is multiline
         let dest = parse(&stringed).unwrap();
         assert_eq!(source, dest);
     }
+
+    #[test]
+    fn test_task_edits() {
+        let mut e = Entry {
+            tasks: vec![
+                Task::Todo(NoNewlines(String::from("a"))),
+                Task::Done(NoNewlines(String::from("b"))),
+                Task::Todo(NoNewlines(String::from("c"))),
+            ],
+            ..Entry::default()
+        };
+
+        e.edit_task(1, NoNewlines(String::from("bee")));
+        assert_eq!(Task::Done(NoNewlines(String::from("bee"))), e.tasks[1]);
+
+        e.move_task(2, 0);
+        assert_eq!(Task::Todo(NoNewlines(String::from("c"))), e.tasks[0]);
+        assert_eq!(Task::Todo(NoNewlines(String::from("a"))), e.tasks[1]);
+
+        let removed = e.remove_task(1);
+        assert_eq!(Task::Todo(NoNewlines(String::from("a"))), removed);
+        assert_eq!(2, e.tasks.len());
+    }
+
+    #[test]
+    fn test_set_and_remove_observations() {
+        let ob = |name: &str, value: &str| Observation {
+            name: ObservationName(String::from(name)),
+            value: NoNewlines(String::from(value)),
+        };
+        let mut e = Entry {
+            observations: vec![ob("weight", "80"), ob("mood", "ok"), ob("weight", "81")],
+            ..Entry::default()
+        };
+
+        e.set_observation(
+            ObservationName(String::from("weight")),
+            NoNewlines(String::from("79")),
+        );
+        assert_eq!(vec![ob("weight", "79"), ob("mood", "ok")], e.observations);
+
+        e.set_observation(
+            ObservationName(String::from("sleep")),
+            NoNewlines(String::from("7h")),
+        );
+        assert_eq!(3, e.observations.len());
+
+        assert_eq!(
+            1,
+            e.remove_observation(&ObservationName(String::from("mood")))
+        );
+        assert_eq!(
+            0,
+            e.remove_observation(&ObservationName(String::from("mood")))
+        );
+        assert_eq!(vec![ob("weight", "79"), ob("sleep", "7h")], e.observations);
+    }
+
+    #[test]
+    fn test_task_order() {
+        let mut e = Entry {
+            tasks: vec![
+                Task::Todo(NoNewlines(String::from("b"))),
+                Task::Working(NoNewlines(String::from("c"))),
+                Task::Todo(NoNewlines(String::from("a"))),
+            ],
+            ..Entry::default()
+        };
+
+        e.order_tasks(TaskOrder::Manual);
+        assert_eq!(Task::Todo(NoNewlines(String::from("b"))), e.tasks[0]);
+
+        e.order_tasks(TaskOrder::Insertion);
+        assert_eq!(Task::Todo(NoNewlines(String::from("b"))), e.tasks[0]);
+        assert_eq!(Task::Todo(NoNewlines(String::from("a"))), e.tasks[2]);
+
+        e.order_tasks(TaskOrder::Sorted);
+        assert_eq!(Task::Working(NoNewlines(String::from("c"))), e.tasks[0]);
+        assert_eq!(Task::Todo(NoNewlines(String::from("a"))), e.tasks[1]);
+    }
 }
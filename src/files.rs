@@ -5,81 +5,187 @@ use std::io;
 use std::io::Write;
 use std::io::{ErrorKind, Read};
 use std::str;
+use zeroize::Zeroizing;
 
+use crate::archive;
+use crate::crypt;
 use crate::entry;
 
+pub fn read_bounded_bytes_from_file<'a>(
+    buf: &'a mut Vec<u8>,
+    filename: &str,
+    max_length: usize,
+) -> Result<&'a [u8], io::Error> {
+    // The buffer only grows as the file is read, so a large max_length
+    // doesn't cost anything for small files.
+    buf.clear();
+    let f = File::open(filename)?;
+    let read_length = f.take(max_length as u64).read_to_end(buf)?;
+
+    if read_length >= max_length {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "file is longer than maximum length allowed",
+        ));
+    }
+
+    Ok(buf)
+}
+
 pub fn read_bounded_str_from_file<'a>(
     buf: &'a mut Vec<u8>,
     filename: &str,
     max_length: usize,
 ) -> Result<&'a str, io::Error> {
-    buf.resize(max_length, 0);
-    let mut chunk = &mut buf[..];
+    let bytes = read_bounded_bytes_from_file(buf, filename, max_length)?;
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            return Err(io::Error::new(ErrorKind::InvalidData, e));
+        }
+    };
+
+    Ok(text)
+}
+
+pub fn is_encrypted_file(filename: &str) -> Result<bool, io::Error> {
+    let mut head = [0u8; 64];
+    let mut f = File::open(filename)?;
     let mut read_length: usize = 0;
-    match File::open(filename) {
-        Ok(mut f) => loop {
-            match f.read(chunk) {
-                Ok(0) => break,
-                Ok(n) => {
-                    read_length += n;
-                    chunk = &mut chunk[n..];
-                }
-                Err(e) if e.kind() == ErrorKind::Interrupted => {}
-                Err(e) => return Err(e),
+    loop {
+        match f.read(&mut head[read_length..]) {
+            Ok(0) => break,
+            Ok(n) => read_length += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(crypt::is_encrypted(&head[..read_length]))
+}
+
+// Returns the text of an entry file, decrypting it if it's encrypted.
+// If the entry has been archived, it's read from the archive.
+// max_size limits the length of the text, not the file.
+pub fn text_from_file(
+    filename: &str,
+    max_size: usize,
+    keys: &crypt::Keyring,
+) -> Result<Zeroizing<String>, io::Error> {
+    let mut buf: Vec<u8> = Vec::new();
+    let max_length = max_size + crypt::OVERHEAD;
+    match read_bounded_bytes_from_file(&mut buf, filename, max_length) {
+        Ok(contents) => text_from_contents(contents, max_size, keys),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            match archive::read_archived_file(filename, max_length)? {
+                Some(contents) => text_from_contents(&contents, max_size, keys),
+                None => Err(e),
             }
-        },
-        Err(read_err) => return Err(read_err),
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Like text_from_file, for the contents of a file that has already been read.
+pub fn text_from_contents(
+    contents: &[u8],
+    max_size: usize,
+    keys: &crypt::Keyring,
+) -> Result<Zeroizing<String>, io::Error> {
+    let plaintext = if crypt::is_encrypted(contents) {
+        keys.decrypt(contents)?
+    } else {
+        Zeroizing::new(contents.to_vec())
     };
 
-    if chunk.is_empty() {
+    if plaintext.len() >= max_size {
         return Err(io::Error::new(
             ErrorKind::InvalidData,
             "file is longer than maximum length allowed",
         ));
     }
 
-    buf.truncate(read_length);
-    let text = match str::from_utf8(buf) {
-        Ok(text) => text,
-        Err(e) => {
-            return Err(io::Error::new(ErrorKind::InvalidData, e));
+    match str::from_utf8(&plaintext) {
+        Ok(text) => Ok(Zeroizing::new(text.to_string())),
+        Err(e) => Err(io::Error::new(ErrorKind::InvalidData, e)),
+    }
+}
+
+// will *not* create a new file. If filename is encrypted,
+// text is encrypted with the same passphrase before it's written.
+pub fn text_to_file(filename: &str, text: &str, keys: &crypt::Keyring) -> Result<(), io::Error> {
+    let encrypted = match is_encrypted_file(filename) {
+        Ok(encrypted) => encrypted,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if archive::read_archived_file(filename, usize::MAX)?.is_some() {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    "this entry has been archived. Use coach unarchive to change it",
+                ));
+            }
+            return Err(e);
         }
+        Err(e) => return Err(e),
     };
 
-    Ok(text)
+    let contents = if encrypted {
+        keys.encrypt(text.as_bytes())?
+    } else {
+        text.as_bytes().to_vec()
+    };
+
+    let mut newfile = OpenOptions::new()
+        .write(true)
+        .create_new(false)
+        .truncate(true)
+        .open(filename)?;
+
+    newfile.write_all(&contents)?;
+    newfile.sync_all()?;
+
+    Ok(())
 }
 
 // will fail if filename already exists
-pub fn new_entry_file(filename: &str, entry: &entry::Entry) -> Result<(), io::Error> {
+pub fn new_entry_file(
+    filename: &str,
+    entry: &entry::Entry,
+    keys: &crypt::Keyring,
+) -> Result<(), io::Error> {
+    let text = entry.to_string();
+    let contents = if keys.encrypts_new_entries() {
+        keys.encrypt(text.as_bytes())?
+    } else {
+        text.into_bytes()
+    };
+
     let mut out = OpenOptions::new()
         .write(true)
         .create_new(true)
-        .open(&filename)?;
-    out.write_all(entry.to_string().as_bytes())?;
+        .open(filename)?;
+    out.write_all(&contents)?;
     out.sync_all()?;
 
     Ok(())
 }
 
-pub fn entry_from_file(filename: &str, max_size: usize) -> Result<entry::Entry, Box<dyn Error>> {
-    let mut buf: Vec<u8> = Vec::new();
-    let text = read_bounded_str_from_file(&mut buf, filename, max_size)?;
-    match entry::parse(text) {
+pub fn entry_from_file(
+    filename: &str,
+    max_size: usize,
+    keys: &crypt::Keyring,
+) -> Result<entry::Entry, Box<dyn Error>> {
+    let text = text_from_file(filename, max_size, keys)?;
+    match entry::parse(&text) {
         Ok(e) => Ok(e),
         Err(e) => Err(Box::new(e)),
     }
 }
 
 // will *not* create a new file.
-pub fn entry_to_file(filename: &str, entry: &entry::Entry) -> Result<(), io::Error> {
-    let mut newfile = OpenOptions::new()
-        .write(true)
-        .create_new(false)
-        .truncate(true)
-        .open(&filename)?;
-
-    newfile.write_all(entry.to_string().as_bytes())?;
-    newfile.sync_all()?;
-
-    Ok(())
+pub fn entry_to_file(
+    filename: &str,
+    entry: &entry::Entry,
+    keys: &crypt::Keyring,
+) -> Result<(), io::Error> {
+    text_to_file(filename, &entry.to_string(), keys)
 }
@@ -0,0 +1,98 @@
+use std::env;
+use std::io;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Coach drives the git command line tool rather than linking against a git
+// library. Everything here works on the local repository only, and never
+// talks to a remote.
+
+fn git(args: &[&str]) -> Result<Output, io::Error> {
+    Command::new("git").args(args).output()
+}
+
+fn git_succeeds(args: &[&str]) -> Result<bool, io::Error> {
+    git(args).map(|out| out.status.success())
+}
+
+fn git_checked(args: &[&str]) -> Result<Output, io::Error> {
+    let out = git(args)?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        return Err(io::Error::other(format!("git failed: {}", stderr.trim())));
+    }
+
+    Ok(out)
+}
+
+// Paths are made absolute so that they mean the same thing to git
+// when it runs in another directory.
+fn absolute(filename: &str) -> Result<String, io::Error> {
+    let path: PathBuf = env::current_dir()?.join(filename);
+    match path.to_str() {
+        Some(s) => Ok(s.to_string()),
+        None => Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "file names must be valid unicode",
+        )),
+    }
+}
+
+pub fn is_repository(dir: &str) -> Result<bool, io::Error> {
+    git_succeeds(&["-C", dir, "rev-parse", "--is-inside-work-tree"])
+}
+
+// Commits the current state of filenames to the repository containing dir,
+// including files that have been removed, with the given message. Any other
+// changes in the repository, staged or not, are left alone. Does nothing if
+// the files haven't changed since the last commit.
+pub fn commit(dir: &str, filenames: &[&str], message: &str) -> Result<(), io::Error> {
+    let mut paths = vec![];
+    for filename in filenames {
+        let path = absolute(filename)?;
+        let exists = PathBuf::from(&path).exists();
+        if exists || git_succeeds(&["-C", dir, "ls-files", "--error-unmatch", "--", &path])? {
+            paths.push(path);
+        }
+    }
+
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let paths: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+    git_checked(&[&["-C", dir, "add", "--all", "--"], &paths[..]].concat())?;
+    let diff = [
+        &["-C", dir, "diff", "--cached", "--quiet", "--"],
+        &paths[..],
+    ]
+    .concat();
+    if git_succeeds(&diff)? {
+        return Ok(());
+    }
+    let commit = ["-C", dir, "commit", "--quiet", "--message", message, "--"];
+    git_checked(&[&commit, &paths[..]].concat())?;
+
+    Ok(())
+}
+
+// Returns one line per commit touching the files in dir, most recent first.
+pub fn log(dir: &str, count: usize) -> Result<Vec<String>, io::Error> {
+    let max_count = format!("--max-count={}", count);
+    let out = git_checked(&[
+        "-C",
+        dir,
+        "log",
+        &max_count,
+        "--date=format:%Y-%m-%d %H:%M",
+        "--format=%ad %s",
+        "--",
+        ".",
+    ])?;
+
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|s| s.to_string())
+        .collect())
+}
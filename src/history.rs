@@ -0,0 +1,260 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use time::OffsetDateTime;
+
+// The history directory holds one numbered subdirectory per change. Each
+// change directory has an "info" file, with the time of the change on the
+// first line, the command that made it on the second, and the names of
+// the files it touched on the remaining lines. The previous contents of the
+// Nth file named in "info" are saved in a file named N. If that file is
+// missing, the entry file didn't exist before the change was made.
+// A change is put together in a directory named after the process making
+// it, and only given its number once the command has succeeded.
+
+// Older changes are discarded once there are more than this many.
+const HISTORY_LIMIT: usize = 100;
+
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub id: u64,
+    pub when: OffsetDateTime,
+    pub command: String,
+    pub filenames: Vec<String>,
+}
+
+fn change_ids(history_dir: &str) -> Result<Vec<u64>, io::Error> {
+    let mut ids = vec![];
+    let dir = match fs::read_dir(history_dir) {
+        Ok(d) => d,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(ids),
+        Err(e) => return Err(e),
+    };
+
+    for dirent in dir {
+        let dirent = dirent?;
+        if let Some(id) = dirent.file_name().to_str().and_then(|s| s.parse().ok()) {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+
+    Ok(ids)
+}
+
+fn read_change(history_dir: &str, id: u64) -> Result<Change, io::Error> {
+    let info_path = Path::new(history_dir).join(id.to_string()).join("info");
+    let info = fs::read_to_string(info_path)?;
+    let mut lines = info.lines();
+    let when = lines
+        .next()
+        .and_then(|s| s.parse().ok())
+        .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok())
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "malformed history timestamp"))?;
+    let command = lines.next().unwrap_or_default().to_string();
+    let filenames = lines.map(|s| s.to_string()).collect();
+
+    Ok(Change {
+        id,
+        when,
+        command,
+        filenames,
+    })
+}
+
+// The contents of some files from just before a command changed them.
+// It's thrown away when dropped, unless it was saved as a change.
+pub struct Snapshot {
+    dir: PathBuf,
+    filenames: Vec<String>,
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Copies the current contents of each of filenames. Call this just
+// before a command overwrites, creates or removes the files, and then
+// save the snapshot once it has.
+pub fn snapshot(history_dir: &str, filenames: &[&str]) -> Result<Snapshot, io::Error> {
+    let snapshot = Snapshot {
+        dir: Path::new(history_dir).join(format!("pending-{}", process::id())),
+        filenames: filenames.iter().map(|f| f.to_string()).collect(),
+    };
+    match fs::remove_dir_all(&snapshot.dir) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    fs::create_dir_all(&snapshot.dir)?;
+
+    for (ix, filename) in filenames.iter().enumerate() {
+        match fs::copy(filename, snapshot.dir.join(ix.to_string())) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(snapshot)
+}
+
+// Records snapshot as a single change made by command.
+pub fn save(history_dir: &str, command: &str, snapshot: Snapshot) -> Result<(), io::Error> {
+    let mut info = File::create(snapshot.dir.join("info"))?;
+    writeln!(info, "{}", OffsetDateTime::now_utc().unix_timestamp())?;
+    writeln!(info, "{}", command.replace('\n', " "))?;
+    for filename in &snapshot.filenames {
+        writeln!(info, "{}", filename)?;
+    }
+    info.sync_all()?;
+
+    let ids = change_ids(history_dir)?;
+    let id = ids.last().map_or(1, |last| last + 1);
+    fs::rename(&snapshot.dir, Path::new(history_dir).join(id.to_string()))?;
+
+    if ids.len() >= HISTORY_LIMIT {
+        for old in &ids[..=ids.len() - HISTORY_LIMIT] {
+            fs::remove_dir_all(Path::new(history_dir).join(old.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Lists saved changes, most recent first.
+pub fn changes(history_dir: &str) -> Result<Vec<Change>, io::Error> {
+    let mut ret = vec![];
+    for id in change_ids(history_dir)?.into_iter().rev() {
+        ret.push(read_change(history_dir, id)?);
+    }
+
+    Ok(ret)
+}
+
+// Restores the files touched by the most recent change to the state
+// they were in before it, and removes that change from the history.
+// Returns the undone change, or None if there is nothing to undo.
+pub fn undo(history_dir: &str) -> Result<Option<Change>, io::Error> {
+    let id = match change_ids(history_dir)?.last() {
+        Some(id) => *id,
+        None => return Ok(None),
+    };
+
+    let change = read_change(history_dir, id)?;
+    let change_dir = Path::new(history_dir).join(id.to_string());
+    for (ix, filename) in change.filenames.iter().enumerate() {
+        let mut saved = match File::open(change_dir.join(ix.to_string())) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                match fs::remove_file(filename) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut contents = vec![];
+        saved.read_to_end(&mut contents)?;
+        let mut out = File::create(filename)?;
+        out.write_all(&contents)?;
+        out.sync_all()?;
+    }
+
+    fs::remove_dir_all(change_dir)?;
+
+    Ok(Some(change))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_restores_previous_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_dir = dir.path().join("history");
+        let history_dir = history_dir.to_str().unwrap();
+        let entry_path = dir.path().join("entry");
+        let entry_name = entry_path.to_str().unwrap();
+
+        fs::write(entry_name, "before").unwrap();
+        let before = snapshot(history_dir, &[entry_name]).unwrap();
+        fs::write(entry_name, "after").unwrap();
+        save(history_dir, "coach task done 1", before).unwrap();
+
+        let undone = undo(history_dir).unwrap().unwrap();
+        assert_eq!("coach task done 1", undone.command);
+        assert_eq!("before", fs::read_to_string(entry_name).unwrap());
+        assert_eq!(None, undo(history_dir).unwrap());
+    }
+
+    #[test]
+    fn test_undo_removes_created_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_dir = dir.path().join("history");
+        let history_dir = history_dir.to_str().unwrap();
+        let entry_path = dir.path().join("entry");
+        let entry_name = entry_path.to_str().unwrap();
+
+        let before = snapshot(history_dir, &[entry_name]).unwrap();
+        fs::write(entry_name, "created").unwrap();
+        save(history_dir, "coach today", before).unwrap();
+
+        undo(history_dir).unwrap().unwrap();
+        assert!(!entry_path.exists());
+    }
+
+    #[test]
+    fn test_changes_most_recent_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_dir = dir.path().join("history");
+        let history_dir = history_dir.to_str().unwrap();
+
+        let entry_path = dir.path().join("entry");
+        let entry_name = entry_path.to_str().unwrap();
+
+        save(
+            history_dir,
+            "first",
+            snapshot(history_dir, &[entry_name]).unwrap(),
+        )
+        .unwrap();
+        save(
+            history_dir,
+            "second",
+            snapshot(history_dir, &[entry_name]).unwrap(),
+        )
+        .unwrap();
+
+        let commands: Vec<String> = changes(history_dir)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.command)
+            .collect();
+        assert_eq!(vec!["second", "first"], commands);
+    }
+
+    #[test]
+    fn test_unsaved_snapshot_is_not_a_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_dir = dir.path().join("history");
+        let history_dir = history_dir.to_str().unwrap();
+        let entry_path = dir.path().join("entry");
+        let entry_name = entry_path.to_str().unwrap();
+
+        fs::write(entry_name, "before").unwrap();
+        drop(snapshot(history_dir, &[entry_name]).unwrap());
+
+        assert!(changes(history_dir).unwrap().is_empty());
+        assert_eq!(0, fs::read_dir(history_dir).unwrap().count());
+    }
+}
@@ -0,0 +1,444 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::crypt;
+use crate::entry;
+use crate::files;
+
+// The index keeps the parsed pieces of every entry in the journal (its
+// label, observations, tasks, events and notes), so that commands that look
+// at many entries don't have to open, check and parse every file every
+// time. An index file begins with a line containing only INDEX_MAGIC. Each
+// entry follows on a line of its own holding, separated by tabs, the
+// entry's file name, modification time in nanoseconds, length and hash,
+// and then either the entry's pieces as JSON or "-" if the entry isn't
+// indexed. Encrypted entries, and entries that can't be parsed, are never
+// stored in the index.
+const INDEX_MAGIC: &str = "#coach-index 2";
+
+// Some filesystems only keep modification times to the nearest two
+// seconds, so an entry changed this close to when the index was written
+// could have changed again since without its time moving. Those entries
+// are always read again and checked against their hash.
+const RACY_NANOS: u128 = 2_000_000_000;
+
+struct Record {
+    modified: u128,
+    length: u64,
+    hash: u64,
+    pieces: Option<Value>,
+}
+
+pub struct Index {
+    filename: String,
+    records: BTreeMap<String, Record>,
+    changed: bool,
+    // When the index file was last written, in nanoseconds.
+    written: u128,
+}
+
+// 64 bit FNV-1a. This only needs to notice changes, it doesn't need to
+// stand up to someone deliberately making collisions.
+pub fn hash(contents: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in contents {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> Result<u128, io::Error> {
+    Ok(metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0))
+}
+
+// Event times have no offset, so they're stored as if they were UTC.
+fn event_time(when: &PrimitiveDateTime) -> i64 {
+    when.assume_utc().unix_timestamp()
+}
+
+fn event_time_from(value: &Value) -> Option<PrimitiveDateTime> {
+    let utc = OffsetDateTime::from_unix_timestamp(value.as_i64()?).ok()?;
+    Some(PrimitiveDateTime::new(utc.date(), utc.time()))
+}
+
+fn pieces(entry: &entry::Entry) -> Value {
+    let observations: Vec<Value> = entry
+        .observations
+        .iter()
+        .map(|ob| json!([ob.name.to_string(), ob.value.to_string()]))
+        .collect();
+    let tasks: Vec<Value> = entry
+        .tasks
+        .iter()
+        .map(|task| json!([task.state(), task.text()]))
+        .collect();
+    let events: Vec<Value> = entry
+        .events
+        .iter()
+        .map(|event| match event {
+            entry::Event::Moment { when, text } => {
+                json!([event_time(when), null, text.to_string()])
+            }
+            entry::Event::Interval { begin, end, text } => {
+                json!([event_time(begin), event_time(end), text.to_string()])
+            }
+        })
+        .collect();
+    let notes: Vec<Value> = entry.notes.iter().map(|n| json!(n.to_string())).collect();
+
+    json!({
+        "label": entry.label.to_string(),
+        "observations": observations,
+        "tasks": tasks,
+        "events": events,
+        "notes": notes,
+    })
+}
+
+fn text_from(value: &Value) -> Option<entry::NoNewlines> {
+    entry::as_no_newlines(value.as_str()?.to_string())
+}
+
+// Builds an entry back up from the pieces stored in the index. Returns
+// None if any of them aren't what an entry could hold, in which case the
+// entry is read from its file instead.
+fn entry_from_pieces(pieces: &Value) -> Option<entry::Entry> {
+    let mut observations = vec![];
+    for ob in pieces["observations"].as_array()? {
+        observations.push(entry::Observation {
+            name: entry::as_observation_name(ob[0].as_str()?.to_string())?,
+            value: text_from(&ob[1])?,
+        });
+    }
+
+    let mut tasks = vec![];
+    for task in pieces["tasks"].as_array()? {
+        let message = text_from(&task[1])?;
+        tasks.push(match task[0].as_str()? {
+            "todo" => entry::Task::Todo(message),
+            "working" => entry::Task::Working(message),
+            "done" => entry::Task::Done(message),
+            "cancelled" => entry::Task::Cancelled(message),
+            _ => return None,
+        });
+    }
+
+    let mut events = vec![];
+    for event in pieces["events"].as_array()? {
+        let text = text_from(&event[2])?;
+        let begin = event_time_from(&event[0])?;
+        events.push(match &event[1] {
+            Value::Null => entry::Event::Moment { when: begin, text },
+            end => entry::Event::Interval {
+                begin,
+                end: event_time_from(end)?,
+                text,
+            },
+        });
+    }
+
+    let mut notes = vec![];
+    for note in pieces["notes"].as_array()? {
+        notes.push(entry::as_note(note.as_str()?.to_string())?);
+    }
+
+    Some(entry::Entry {
+        label: text_from(&pieces["label"])?,
+        observations,
+        tasks,
+        events,
+        notes,
+    })
+}
+
+fn parse_records(text: &str) -> Option<BTreeMap<String, Record>> {
+    let mut records = BTreeMap::new();
+    for line in text.strip_prefix(INDEX_MAGIC)?.strip_prefix('\n')?.lines() {
+        let fields: Vec<&str> = line.splitn(5, '\t').collect();
+        if fields.len() != 5 {
+            return None;
+        }
+
+        let record = Record {
+            modified: fields[1].parse().ok()?,
+            length: fields[2].parse().ok()?,
+            hash: u64::from_str_radix(fields[3], 16).ok()?,
+            pieces: match fields[4] {
+                "-" => None,
+                pieces => Some(serde_json::from_str(pieces).ok()?),
+            },
+        };
+        records.insert(fields[0].to_string(), record);
+    }
+
+    Some(records)
+}
+
+impl Index {
+    // Starts an index with no entries. It will be written to filename.
+    pub fn empty(filename: &str) -> Index {
+        Index {
+            filename: filename.to_string(),
+            records: BTreeMap::new(),
+            changed: true,
+            written: 0,
+        }
+    }
+
+    // Reads the index stored in filename. If there is no index there yet,
+    // or it can't be read, returns an empty one that will be rebuilt as
+    // it's used.
+    pub fn open(filename: &str) -> Result<Index, io::Error> {
+        let text = match fs::read_to_string(filename) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Index::empty(filename)),
+            Err(e) if e.kind() == ErrorKind::InvalidData => return Ok(Index::empty(filename)),
+            Err(e) => return Err(e),
+        };
+
+        match parse_records(&text) {
+            Some(records) => Ok(Index {
+                filename: filename.to_string(),
+                records,
+                changed: false,
+                written: modified_nanos(&fs::metadata(filename)?)?,
+            }),
+            None => Ok(Index::empty(filename)),
+        }
+    }
+
+    // Returns the entry in journal_dir named name. Files that have the same
+    // modification time and length as when they were indexed aren't read
+    // again, unless they were changed too soon before the index was
+    // written to be sure. Encrypted entries are always read from their
+    // files.
+    pub fn entry(
+        &mut self,
+        journal_dir: &str,
+        name: &str,
+        max_size: usize,
+        keys: &crypt::Keyring,
+    ) -> Result<entry::Entry, Box<dyn Error>> {
+        let path = Path::new(journal_dir).join(name);
+        let filename = path.to_str().unwrap();
+        let metadata = fs::metadata(filename)?;
+        let modified = modified_nanos(&metadata)?;
+        let length = metadata.len();
+        let racy = modified.saturating_add(RACY_NANOS) > self.written;
+
+        if let Some(Record {
+            modified: m,
+            length: l,
+            pieces: Some(pieces),
+            ..
+        }) = self.records.get(name)
+        {
+            if *m == modified && *l == length && !racy {
+                if let Some(entry) = entry_from_pieces(pieces) {
+                    return Ok(entry);
+                }
+            }
+        }
+
+        let mut buf: Vec<u8> = Vec::new();
+        let contents =
+            files::read_bounded_bytes_from_file(&mut buf, filename, max_size + crypt::OVERHEAD)?;
+        let contents_hash = hash(contents);
+        let text = files::text_from_contents(contents, max_size, keys)?;
+        let parsed = entry::parse(&text);
+        let indexed_pieces = match &parsed {
+            Ok(entry) if !crypt::is_encrypted(contents) => Some(pieces(entry)),
+            _ => None,
+        };
+
+        let unchanged = match self.records.get(name) {
+            Some(record) => record.hash == contents_hash && record.modified == modified,
+            None => false,
+        };
+        if !unchanged {
+            self.records.insert(
+                name.to_string(),
+                Record {
+                    modified,
+                    length,
+                    hash: contents_hash,
+                    pieces: indexed_pieces,
+                },
+            );
+            self.changed = true;
+        } else if racy {
+            // Writing the index again puts its time far enough past the
+            // entry's that it can be trusted next time.
+            self.changed = true;
+        }
+
+        Ok(parsed?)
+    }
+
+    // Forgets any entries whose names aren't in names.
+    pub fn retain(&mut self, names: &[String]) {
+        let before = self.records.len();
+        self.records.retain(|name, _| names.contains(name));
+        if self.records.len() != before {
+            self.changed = true;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // Writes the index back to its file, if anything in it has changed.
+    pub fn save(&mut self) -> Result<(), io::Error> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let path = Path::new(&self.filename);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut out = String::new();
+        out.push_str(INDEX_MAGIC);
+        out.push('\n');
+        for (name, record) in self.records.iter() {
+            let pieces = match &record.pieces {
+                Some(pieces) => pieces.to_string(),
+                None => String::from("-"),
+            };
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{:x}\t{}\n",
+                name, record.modified, record.length, record.hash, pieces
+            ));
+        }
+
+        // Write a new file and move it into place, so that an interrupted
+        // save can't leave a half written index behind.
+        let tmp_filename = format!("{}.tmp", self.filename);
+        let mut tmp = fs::File::create(&tmp_filename)?;
+        tmp.write_all(out.as_bytes())?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_filename, &self.filename)?;
+        self.changed = false;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "#coach\n2021-10-31\nmood: fine\n\nTODO x\nDONE y\n\n\
+                        * <2021-10-31 Sun 09:30> started\n\
+                        * <2021-10-31 Sun 10:00>--<2021-10-31 Sun 11:15> met\n\nsome notes\n\n";
+
+    #[test]
+    fn test_pieces_roundtrip() {
+        let entry = entry::parse(TEXT).unwrap();
+        assert_eq!(
+            Some(entry),
+            entry_from_pieces(&pieces(&entry::parse(TEXT).unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_dir = dir.path().to_str().unwrap();
+        let index_path = dir.path().join(".coach").join("index");
+        let index_filename = index_path.to_str().unwrap();
+        let keys = crypt::Keyring::new(None, false);
+        fs::write(dir.path().join("2021-10-31"), TEXT).unwrap();
+
+        let mut index = Index::open(index_filename).unwrap();
+        let entry = index.entry(journal_dir, "2021-10-31", 1024, &keys).unwrap();
+        assert_eq!(TEXT, entry.to_string());
+        index.save().unwrap();
+
+        let reopened = Index::open(index_filename).unwrap();
+        assert_eq!(1, reopened.len());
+        let record = reopened.records.get("2021-10-31").unwrap();
+        assert_eq!(
+            Some(entry),
+            entry_from_pieces(record.pieces.as_ref().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_index_notices_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_dir = dir.path().to_str().unwrap();
+        let index_path = dir.path().join("index");
+        let keys = crypt::Keyring::new(None, false);
+        let entry_path = dir.path().join("2021-10-31");
+        fs::write(&entry_path, "#coach\n2021-10-31\n\n").unwrap();
+
+        let mut index = Index::open(index_path.to_str().unwrap()).unwrap();
+        index.entry(journal_dir, "2021-10-31", 1024, &keys).unwrap();
+        fs::write(&entry_path, "#coach\n2021-10-31\n\nDONE y\n\n").unwrap();
+        let entry = index.entry(journal_dir, "2021-10-31", 1024, &keys).unwrap();
+        assert_eq!("#coach\n2021-10-31\n\nDONE y\n\n", entry.to_string());
+    }
+
+    #[test]
+    fn test_index_notices_changes_within_the_same_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_dir = dir.path().to_str().unwrap();
+        let index_path = dir.path().join("index");
+        let index_filename = index_path.to_str().unwrap();
+        let keys = crypt::Keyring::new(None, false);
+        let entry_path = dir.path().join("2021-10-31");
+        fs::write(&entry_path, "#coach\n2021-10-31\n\nTODO x\n\n").unwrap();
+        let modified =
+            filetime::FileTime::from_last_modification_time(&fs::metadata(&entry_path).unwrap());
+
+        let mut index = Index::open(index_filename).unwrap();
+        index.entry(journal_dir, "2021-10-31", 1024, &keys).unwrap();
+        index.save().unwrap();
+
+        // The same length, and the same modification time.
+        fs::write(&entry_path, "#coach\n2021-10-31\n\nDONE x\n\n").unwrap();
+        filetime::set_file_mtime(&entry_path, modified).unwrap();
+
+        let mut index = Index::open(index_filename).unwrap();
+        let entry = index.entry(journal_dir, "2021-10-31", 1024, &keys).unwrap();
+        assert_eq!("#coach\n2021-10-31\n\nDONE x\n\n", entry.to_string());
+    }
+
+    #[test]
+    fn test_malformed_index_is_empty() {
+        assert!(parse_records("#coach-index 2\nnonsense\n").is_none());
+        assert!(parse_records("#coach-index 2\n2021-10-31\t1\t2\t3\t{\"label\"\n").is_none());
+        assert!(parse_records("#coach-index 1\n").is_none());
+    }
+
+    #[test]
+    fn test_bad_pieces_are_ignored() {
+        let pieces = json!({
+            "label": "2021-10-31",
+            "observations": [],
+            "tasks": [["later", "x"]],
+            "events": [],
+            "notes": [],
+        });
+        assert!(entry_from_pieces(&pieces).is_none());
+    }
+}
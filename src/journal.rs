@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::Date;
+
+use crate::archive;
+use crate::crypt;
+use crate::entry;
+use crate::files;
+use crate::index;
+
+// Daily entries are named, and labelled, after their date in this format.
+pub const DATE_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month repr:numerical]-[day]");
+
+// A journal is a directory of daily entries, named after their dates,
+// along with any older entries that have been archived. Other files in
+// the directory are ignored.
+pub struct JournalEntry {
+    pub name: String,
+    pub date: Date,
+    pub entry: entry::Entry,
+}
+
+// An entry or archive that couldn't be read, and why.
+pub struct Problem {
+    pub name: String,
+    pub error: Box<dyn Error>,
+}
+
+pub fn entry_date(name: &str) -> Option<Date> {
+    Date::parse(name, &DATE_FORMAT).ok()
+}
+
+// Returns the names of the entries in journal_dir, in date order.
+pub fn entry_names(journal_dir: &str) -> Result<Vec<String>, io::Error> {
+    let mut names = vec![];
+    for dirent in fs::read_dir(journal_dir)? {
+        let dirent = dirent?;
+        if !dirent.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(name) = dirent.file_name().to_str() {
+            if entry_date(name).is_some() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+// Returns the name of the latest entry in journal_dir from before date, if
+// there is one. Archived entries aren't included.
+pub fn previous_entry_name(journal_dir: &str, date: Date) -> Result<Option<String>, io::Error> {
+    Ok(entry_names(journal_dir)?
+        .into_iter()
+        .rev()
+        .find(|name| entry_date(name).is_some_and(|d| d < date)))
+}
+
+// Reads every entry in journal_dir, including archived entries, in date
+// order, using and updating the index stored in index_filename. Entries
+// that can't be read or parsed are returned as problems rather than
+// failing the whole journal. An entry that is both loose and archived, or
+// in more than one archive, is only read once, preferring the loose file.
+pub fn read_entries(
+    journal_dir: &str,
+    index_filename: &str,
+    max_size: usize,
+    keys: &crypt::Keyring,
+) -> Result<(Vec<JournalEntry>, Vec<Problem>), io::Error> {
+    let mut index = index::Index::open(index_filename)?;
+    let ret = read_entries_with_index(journal_dir, &mut index, max_size, keys)?;
+    index.save()?;
+
+    Ok(ret)
+}
+
+// Throws away the index in index_filename and builds a new one.
+// Returns the number of entries indexed.
+pub fn reindex(
+    journal_dir: &str,
+    index_filename: &str,
+    max_size: usize,
+    keys: &crypt::Keyring,
+) -> Result<(usize, Vec<Problem>), io::Error> {
+    let mut index = index::Index::empty(index_filename);
+    let (entries, problems) = read_entries_with_index(journal_dir, &mut index, max_size, keys)?;
+    index.save()?;
+
+    Ok((entries.len(), problems))
+}
+
+fn read_entries_with_index(
+    journal_dir: &str,
+    index: &mut index::Index,
+    max_size: usize,
+    keys: &crypt::Keyring,
+) -> Result<(Vec<JournalEntry>, Vec<Problem>), io::Error> {
+    let names = entry_names(journal_dir)?;
+    index.retain(&names);
+    let mut seen: BTreeSet<String> = names.iter().cloned().collect();
+
+    let mut entries = vec![];
+    let mut problems = vec![];
+    for name in names.iter().cloned() {
+        match index.entry(journal_dir, &name, max_size, keys) {
+            Ok(entry) => entries.push(JournalEntry {
+                date: entry_date(&name).unwrap(),
+                name,
+                entry,
+            }),
+            Err(error) => problems.push(Problem { name, error }),
+        }
+    }
+
+    // Archived entries aren't indexed, since archives are already
+    // quicker to read than the loose files they replaced.
+    for archive_filename in archive::archive_filenames(journal_dir)? {
+        let archived = match archive::read_archive(&archive_filename, max_size + crypt::OVERHEAD) {
+            Ok(archived) => archived,
+            Err(e) => {
+                problems.push(Problem {
+                    name: archive_filename,
+                    error: Box::new(e),
+                });
+                continue;
+            }
+        };
+
+        for (name, contents) in archived {
+            let date = match entry_date(&name) {
+                Some(date) if seen.insert(name.clone()) => date,
+                _ => continue,
+            };
+            let parsed = files::text_from_contents(&contents, max_size, keys)
+                .map_err(|e| -> Box<dyn Error> { Box::new(e) })
+                .and_then(|text| {
+                    entry::parse(&text).map_err(|e| -> Box<dyn Error> { Box::new(e) })
+                });
+            match parsed {
+                Ok(entry) => entries.push(JournalEntry { name, date, entry }),
+                Err(error) => problems.push(Problem { name, error }),
+            }
+        }
+    }
+    entries.sort_by_key(|e| e.date);
+
+    Ok((entries, problems))
+}
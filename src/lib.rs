@@ -1,3 +1,24 @@
+pub mod archive;
+pub mod carried;
+pub mod chart;
+pub mod completions;
+pub mod config;
+pub mod crypt;
+pub mod dates;
+pub mod diff;
 pub mod editor;
 pub mod entry;
 pub mod files;
+pub mod git;
+pub mod history;
+pub mod index;
+pub mod journal;
+pub mod output;
+pub mod query;
+pub mod report;
+pub mod search;
+pub mod standup;
+pub mod stats;
+pub mod tasks;
+pub mod timesheet;
+pub mod zoneinfo;
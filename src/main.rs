@@ -1,20 +1,49 @@
-use clap::{App, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, SubCommand};
+use std::env;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::io;
 use time::format_description::FormatItem;
 use time::macros::format_description;
-use time::{Duration, OffsetDateTime};
+use time::OffsetDateTime;
+use zeroize::Zeroizing;
 
+use coach::archive;
+use coach::carried;
+use coach::chart;
+use coach::completions;
+use coach::config;
+use coach::crypt;
+use coach::dates;
+use coach::diff;
 use coach::editor;
 use coach::entry;
 use coach::files;
+use coach::git;
+use coach::history;
+use coach::journal;
+use coach::journal::DATE_FORMAT;
+use coach::output;
+use coach::query;
+use coach::report;
+use coach::search;
+use coach::standup;
+use coach::stats;
+use coach::tasks;
+use coach::timesheet;
+use coach::zoneinfo;
 
-// A typical entry made by hand right now is around 1-2K
-const MAX_ENTRY_SIZE_BYTES: usize = 8 * 1024;
+// These are inside the journal directory.
+const HISTORY_DIR: &str = ".coach/history";
 
-const DATE_FORMAT: &[FormatItem<'static>] =
-    format_description!("[year]-[month repr:numerical]-[day]");
+const INDEX_FILE: &str = ".coach/index";
+const CARRIED_FILE: &str = ".coach/carried";
+
+const CLOCK_FORMAT: &[FormatItem<'static>] = format_description!("[hour repr:24]:[minute]");
+
+const HISTORY_TIME_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month repr:numerical]-[day] [hour repr:24]:[minute]");
 
 fn no_newline_validator(val: String) -> Result<(), String> {
     match entry::as_no_newlines(val) {
@@ -23,6 +52,50 @@ fn no_newline_validator(val: String) -> Result<(), String> {
     }
 }
 
+fn index_validator(val: String) -> Result<(), String> {
+    match val.parse::<usize>() {
+        Ok(ix) if ix > 0 => Ok(()),
+        _ => Err(String::from("indexes are numbers starting at 1")),
+    }
+}
+
+fn days_validator(val: String) -> Result<(), String> {
+    match val.parse::<u32>() {
+        Ok(_) => Ok(()),
+        _ => Err(String::from("days must be a whole number")),
+    }
+}
+
+// Anything made only of digits, commas and dashes is taken to be a list
+// of indexes, so it has to be a valid one. Anything else is task text.
+fn task_selection_validator(val: String) -> Result<(), String> {
+    let looks_like_indexes = val
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == ',' || c == '-' || c == ' ');
+    if looks_like_indexes && tasks::parse_indexes(&val).is_none() {
+        return Err(String::from(
+            "task indexes are numbers starting at 1, or lists and ranges of them like 2-4,7",
+        ));
+    }
+    no_newline_validator(val)
+}
+
+fn timezone_validator(val: String) -> Result<(), String> {
+    match dates::parse_timezone(&val) {
+        Some(_) => Ok(()),
+        None => Err(String::from(
+            "time zones must be local, UTC, an offset like +05:30, or a name like America/Los_Angeles",
+        )),
+    }
+}
+
+fn time_of_day_validator(val: String) -> Result<(), String> {
+    match dates::parse_time_of_day(&val) {
+        Some(_) => Ok(()),
+        None => Err(String::from("times must look like 04:00")),
+    }
+}
+
 fn observation_name_validator(val: String) -> Result<(), String> {
     match entry::as_observation_name(val) {
         Some(_) => Ok(()),
@@ -30,6 +103,34 @@ fn observation_name_validator(val: String) -> Result<(), String> {
     }
 }
 
+// Settings most commands need, once they've been read from flags, the
+// environment and the config file and checked.
+struct Settings {
+    journal_dir: String,
+    history_dir: String,
+    index_file: String,
+    carried_file: String,
+    max_size: usize,
+    order: entry::TaskOrder,
+    editor: String,
+}
+
+// Everything a command needs to run: its settings, the keys for encrypted
+// entries, which entry it's about, and when it's being run.
+struct Context {
+    settings: Settings,
+    config: config::Config,
+    keys: crypt::Keyring,
+    format: output::Format,
+    autocommit: bool,
+    entryname: String,
+    label: String,
+    date: time::Date,
+    today: time::Date,
+    when: OffsetDateTime,
+    guessed_zone: bool,
+}
+
 #[derive(Debug)]
 struct CommandError {
     desc: String,
@@ -55,57 +156,142 @@ impl Error for CommandError {
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut app = App::new("coach")
-        .about("a journal and project manager")
-        .long_about(
-            "Coach is a semi-structured productivity journal file format, and a command
+// The command line, without the hidden __complete command.
+fn app() -> App<'static, 'static> {
+    App::new("coach")
+    .about("a journal and project manager")
+    .long_about(
+        "Coach is a semi-structured productivity journal file format, and a command
 line tool for managing coach files. You can use coach to keep track of a daily
 TODO list, keep a record of observations of key metrics, and keep daily 
 progress notes.",
-        )
-        .arg(
-            Arg::with_name("entry")
-                .long("entry")
-                .short("f")
-                .takes_value(true)
-                .value_name("FILENAME")
-                .help("filename of entry to use. If not provided, use a file named after the current UTC date in the current working directory"),
-        )
-        .arg(
-            Arg::with_name("yesterday").long("yesterday").takes_value(false).conflicts_with("entry").help("use the entry named by the previous day, in UTC"),
-        )
-        .subcommand(
-            SubCommand::with_name("today")
-                .about("creates a new journal file in the current working directory")
-                .long_about(
-                    "today will create a new daily entry file in the current working directory,
-named after the current date. Other commands will write to or edit that file.",
-                ).arg(
-                Arg::with_name("from_file")
-                .long("from_file")
-                .short("r")
-                .takes_value(true)
-                .value_name("FILENAME")
-                .help("migrate TODO and WORKING tasks from FILENAME")
+    )
+    .arg(
+        Arg::with_name("config")
+            .long("config")
+            .takes_value(true)
+            .value_name("FILENAME")
+            .env("COACH_CONFIG")
+            .help("read settings from FILENAME, rather than ~/.config/coach/config.toml"),
+    )
+    .arg(
+        Arg::with_name("journal_dir")
+            .long("journal-dir")
+            .takes_value(true)
+            .value_name("DIR")
+            .help("the directory holding the journal's entries. Defaults to the current working directory. Also set by COACH_JOURNAL_DIR, or journal_dir in the config file"),
+    )
+    .arg(
+        Arg::with_name("entry")
+            .long("entry")
+            .short("f")
+            .takes_value(true)
+            .value_name("FILENAME")
+            .help("filename of entry to use. If not provided, use a file named after the current date in the journal directory"),
+    )
+    .arg(
+        Arg::with_name("yesterday").long("yesterday").takes_value(false).conflicts_with("entry").help("use the entry named by the previous day"),
+    )
+    .arg(
+        Arg::with_name("timezone")
+            .long("timezone")
+            .takes_value(true)
+            .value_name("TIMEZONE")
+            .allow_hyphen_values(true)
+            .validator(timezone_validator)
+            .help("the time zone used to decide what day it is, and for event times. TIMEZONE can be local, UTC, an offset like -08:00, or a name like America/Los_Angeles. If not provided, use the local time zone, or UTC if it can't be found. Also set by COACH_TIMEZONE, or timezone in the config file"),
+    )
+    .arg(
+        Arg::with_name("day_starts")
+            .long("day-starts")
+            .takes_value(true)
+            .value_name("TIME")
+            .validator(time_of_day_validator)
+            .help("the time of day when one day's entry ends and the next begins, like 04:00. Defaults to midnight. Also set by COACH_DAY_STARTS, or day_starts in the config file"),
+    )
+    .arg(
+        Arg::with_name("date")
+            .long("date")
+            .short("d")
+            .takes_value(true)
+            .value_name("DATE")
+            .allow_hyphen_values(true)
+            .conflicts_with_all(&["entry", "yesterday"])
+            .help("use the entry named by DATE. DATE can be a date like 2021-10-31, a number of days from today like -3, yesterday, a day of the week like friday for the most recent friday, last friday, or next friday"),
+    )
+    .arg(
+        Arg::with_name("task_order")
+            .long("task-order")
+            .takes_value(true)
+            .value_name("ORDER")
+            .possible_values(&config::TASK_ORDERS)
+            .help("how tasks are kept in order. sorted puts them in order by state, then text, after every change. insertion keeps them in the order they were added. manual leaves them wherever coach task mv puts them. Defaults to sorted. Also set by COACH_TASK_ORDER, or task_order in the config file"),
+    )
+    .arg(
+        Arg::with_name("output")
+            .long("output")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["text", "json", "ndjson"])
+            .default_value("text")
+            .help("how listing commands like coach task and coach cat print what they find. json prints a single JSON value, and ndjson prints one JSON record per line. See the README for the fields in each record"),
+    )
+    .arg(
+        Arg::with_name("key_file").long("key-file").takes_value(true).value_name("FILENAME").env("COACH_KEY_FILE").help("read the passphrase for encrypted entries from FILENAME, rather than asking for it"),
+    )
+    .arg(
+        Arg::with_name("encrypt").long("encrypt").takes_value(false).help("encrypt new entries with a passphrase. You can also turn this on by setting COACH_ENCRYPT in your environment. Existing entries stay encrypted or unencrypted as they are"),
+    )
+    .arg(
+        Arg::with_name("autocommit").long("autocommit").takes_value(false).help("commit every change to the git repository containing the journal. You can also turn this on by setting COACH_AUTOCOMMIT in your environment"),
+    )
+    .subcommand(
+        SubCommand::with_name("today")
+            .about("creates a new journal file in the journal directory")
+            .long_about(
+                "today will create a new daily entry file in the journal directory, named
+after the current date. Other commands will write to or edit that file. The
+journal directory is the current working directory, unless it's set with
+--journal-dir, COACH_JOURNAL_DIR or journal_dir in the config file.",
             ).arg(
-                Arg::with_name("from_yesterday")
-                .long("from_yesterday")
-                .short("m")
-                .takes_value(false)
-                .conflicts_with("from_file")
-                .help("migrate TODO and WORKING tasks from a file named after yesterday UTC in the current directory")
-            )
+            Arg::with_name("from_file")
+            .long("from_file")
+            .short("r")
+            .takes_value(true)
+            .value_name("FILENAME")
+            .help("migrate TODO and WORKING tasks from FILENAME")
+        ).arg(
+            Arg::with_name("from_yesterday")
+            .long("from_yesterday")
+            .short("m")
+            .takes_value(false)
+            .conflicts_with("from_file")
+            .help("migrate TODO and WORKING tasks from a file named after yesterday in the journal directory. If --date is used, migrate from the day before DATE")
+        ).arg(
+            Arg::with_name("no_migrate")
+            .long("no_migrate")
+            .takes_value(false)
+            .conflicts_with_all(&["from_file", "from_yesterday", "from_date"])
+            .help("don't migrate any tasks, even if today.from_yesterday is set in the config file")
+        ).arg(
+            Arg::with_name("from_date")
+            .long("from_date")
+            .takes_value(true)
+            .value_name("DATE")
+            .allow_hyphen_values(true)
+            .conflicts_with_all(&["from_file", "from_yesterday"])
+            .help("migrate TODO and WORKING tasks from the entry named by DATE, which can be written any way --date can")
         )
-        .subcommand(
-            SubCommand::with_name("cat")
-                .about("writes the contents of the current journal entry to standard out"),
-        )
-        .subcommand(
-            SubCommand::with_name("observe")
-                .about("adds a key/value observation to the journal")
-                .long_about(
-                    "coach observe adds a key / value pair to the current journal entry. You can
+    )
+    .subcommand(
+        SubCommand::with_name("cat")
+            .about("writes the contents of the current journal entry to standard out"),
+    )
+    .subcommand(
+        SubCommand::with_name("observe")
+            .about("adds a key/value observation to the journal")
+            .long_about(
+                "coach observe adds a key / value pair to the current journal entry. You can
 use observations to keep track of key project metrics over time. For example,
 to add an observation about the weather to your entry, you could use:
 
@@ -114,25 +300,55 @@ to add an observation about the weather to your entry, you could use:
 To see a list of all of the observations in the current entry, use
 
     coach observe
+
+To correct an observation, replacing its value rather than adding another,
+use coach observe --set, and to remove one, use coach observe --rm. To see how
+an observation has changed over time, use
+
+    coach observe --history weather
 ",
-                )
-                .arg(
-                    Arg::with_name("NAME")
-                        .requires("VALUE")
-                        .validator(observation_name_validator)
-                        .index(1),
-                )
-                .arg(
-                    Arg::with_name("VALUE")
-                        .validator(no_newline_validator)
-                        .index(2),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("task")
-                .about("manages the TODO list from this entry")
-                .long_about(
-                    "You can use coach task to create new tasks on the to-do list for the current
+            )
+            .arg(
+                Arg::with_name("NAME")
+                    .requires("VALUE")
+                    .validator(observation_name_validator)
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("VALUE")
+                    .validator(no_newline_validator)
+                    .index(2),
+            )
+            .arg(
+                Arg::with_name("history")
+                    .long("history")
+                    .takes_value(true)
+                    .value_name("NAME")
+                    .validator(observation_name_validator)
+                    .conflicts_with("NAME")
+                    .help("list the values of the observation named NAME from every entry in the journal, in date order"),
+            )
+            .arg(
+                Arg::with_name("set")
+                    .long("set")
+                    .requires("NAME")
+                    .help("replace the value of the observation named NAME, rather than adding another"),
+            )
+            .arg(
+                Arg::with_name("rm")
+                    .long("rm")
+                    .takes_value(true)
+                    .value_name("NAME")
+                    .validator(observation_name_validator)
+                    .conflicts_with_all(&["NAME", "history", "set"])
+                    .help("remove every observation named NAME from the entry"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("task")
+            .about("manages the TODO list from this entry")
+            .long_about(
+                "You can use coach task to create new tasks on the to-do list for the current
 entry, view the day's tasks, and change the state of existing tasks.
 
 coach tasks are either TODO (you need to get to them), DONE (already completed),
@@ -145,42 +361,161 @@ You can make changes to individual tasks using their indexes - for example,
 you can set the second task listed by 'coach task' to DONE with:
 
     coach task done 2
+
+You can also use text from the task's message instead of its index, as long
+as it only matches one task:
+
+    coach task done README
+
+Tasks that are migrated from day to day remember the day they were first
+seen. To list the tasks that have been carried for more than a week, use:
+
+    coach task --stale 7
 ",
-                )
-                .subcommand(
-                    SubCommand::with_name("new").about("create a new task").arg(
+            )
+            .arg(
+                Arg::with_name("stale")
+                    .long("stale")
+                    .takes_value(true)
+                    .value_name("DAYS")
+                    .validator(days_validator)
+                    .help("only list TODO and WORKING tasks first seen more than DAYS days before this entry"),
+            )
+            .subcommand(
+                SubCommand::with_name("new").about("create a new task").arg(
+                    Arg::with_name("MESSAGE")
+                        .required(true)
+                        .validator(no_newline_validator)
+                        .index(1),
+                ),
+            )
+            .subcommand(
+                SubCommand::with_name("todo")
+                    .about("mark tasks as TODO")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required_unless("match")
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, a list of indexes and ranges like 2-4,7, or text from the task's message that only matches one task"),
+                    )
+                    .arg(
+                        Arg::with_name("match")
+                            .long("match")
+                            .takes_value(true)
+                            .value_name("TEXT")
+                            .conflicts_with("TASK")
+                            .help("mark every task containing TEXT, ignoring case"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("working")
+                    .about("mark tasks as WORKING")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required_unless("match")
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, a list of indexes and ranges like 2-4,7, or text from the task's message that only matches one task"),
+                    )
+                    .arg(
+                        Arg::with_name("match")
+                            .long("match")
+                            .takes_value(true)
+                            .value_name("TEXT")
+                            .conflicts_with("TASK")
+                            .help("mark every task containing TEXT, ignoring case"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("done")
+                    .about("mark tasks as DONE")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required_unless("match")
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, a list of indexes and ranges like 2-4,7, or text from the task's message that only matches one task"),
+                    )
+                    .arg(
+                        Arg::with_name("match")
+                            .long("match")
+                            .takes_value(true)
+                            .value_name("TEXT")
+                            .conflicts_with("TASK")
+                            .help("mark every task containing TEXT, ignoring case"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("cancel")
+                    .about("mark tasks as CANCELLED")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required_unless("match")
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, a list of indexes and ranges like 2-4,7, or text from the task's message that only matches one task"),
+                    )
+                    .arg(
+                        Arg::with_name("match")
+                            .long("match")
+                            .takes_value(true)
+                            .value_name("TEXT")
+                            .conflicts_with("TASK")
+                            .help("mark every task containing TEXT, ignoring case"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("edit")
+                    .about("change the message of a task, keeping its state")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required(true)
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, or text from the task's message that only matches one task"),
+                    )
+                    .arg(
                         Arg::with_name("MESSAGE")
                             .required(true)
                             .validator(no_newline_validator)
-                            .index(1),
+                            .index(2),
                     ),
-                )
-                .subcommand(
-                    SubCommand::with_name("todo")
-                        .about("mark a task as TODO")
-                        .arg(Arg::with_name("INDEX").required(true).index(1)),
-                )
-                .subcommand(
-                    SubCommand::with_name("working")
-                        .about("mark a task as WORKING")
-                        .arg(Arg::with_name("INDEX").required(true).index(1)),
-                )
-                .subcommand(
-                    SubCommand::with_name("done")
-                        .about("mark a task as DONE")
-                        .arg(Arg::with_name("INDEX").required(true).index(1)),
-                )
-                .subcommand(
-                    SubCommand::with_name("cancel")
-                        .about("mark a task as CANCELLED")
-                        .arg(Arg::with_name("INDEX").required(true).index(1)),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("event")
-                .about("lists events, or makes note of a new event")
-                .long_about(
-                    "Coach events are brief notes that include a timestamp. You can use them for
+            )
+            .subcommand(
+                SubCommand::with_name("rm")
+                    .about("remove a task from the entry")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required(true)
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, or text from the task's message that only matches one task"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("mv")
+                    .about("move a task to another position in the list")
+                    .arg(
+                        Arg::with_name("TASK")
+                            .required(true)
+                            .validator(task_selection_validator)
+                            .index(1)
+                            .help("the index of the task, or text from the task's message that only matches one task"),
+                    )
+                    .arg(
+                        Arg::with_name("POSITION")
+                            .required(true)
+                            .validator(index_validator)
+                            .index(2),
+                    ),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("event")
+            .about("lists events, or makes note of a new event")
+            .long_about(
+                "Coach events are brief notes that include a timestamp. You can use them for
 simple time tracking, or to check in during your work. To list all of the
 events in an entry, use:
 
@@ -190,246 +525,2153 @@ To make note of a new event, include a message as an argument, like this:
 
     coach event \"wrote about text for the event command\"
 ",
-                )
-                .arg(
-                    Arg::with_name("MESSAGE")
-                        .validator(no_newline_validator)
-                        .index(1),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("note")
-                .about("add a note to this entry")
-                .long_about(
-                    "note will open a text editor and allow you to add one or more notes,
+            )
+            .arg(
+                Arg::with_name("MESSAGE")
+                    .validator(no_newline_validator)
+                    .index(1),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("note")
+            .about("add a note to this entry")
+            .long_about(
+                "note will open a text editor and allow you to add one or more notes,
 to the current entry. You can separate notes by blank lines.",
-                ).arg(
-                    Arg::with_name("message")
-                    .short("m")
-                    .long("message")
+            ).arg(
+                Arg::with_name("message")
+                .short("m")
+                .long("message")
+                .takes_value(true)
+                .value_name("MESSAGE")
+                .help("if provided, use the argument value for the note content rather than opening an editor")
+            )
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about("list the notes in this entry, with their indexes and first lines"),
+            )
+            .subcommand(
+                SubCommand::with_name("edit")
+                    .about("open a note in a text editor")
+                    .arg(
+                        Arg::with_name("INDEX")
+                            .required(true)
+                            .validator(index_validator)
+                            .index(1),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("rm")
+                    .about("remove a note from this entry")
+                    .arg(
+                        Arg::with_name("INDEX")
+                            .required(true)
+                            .validator(index_validator)
+                            .index(1),
+                    ),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("edit").about("opens the current coach entry with a text editor. This could corrupt your file, so be careful!"),
+    )
+    .subcommand(
+        SubCommand::with_name("undo")
+            .about("reverts the most recent change made by a coach command")
+            .long_about(
+                "Before coach changes an entry file, it saves the previous version of the file
+in the .coach/history directory. coach undo restores the files changed by the
+most recent command to the state they were in before it ran. You can run
+coach undo more than once to step further back.",
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("history")
+            .about("lists recent changes that can be reverted with coach undo")
+            .arg(
+                Arg::with_name("count")
+                    .long("count")
+                    .short("n")
                     .takes_value(true)
-                    .value_name("MESSAGE")
-                    .help("if provided, use the argument value for the note content rather than opening an editor")
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("edit").about("opens the current coach entry with a text editor. This could corrupt your file, so be careful!"),
-        );
-    let matches = app.clone().get_matches();
-
-    let when: OffsetDateTime =
-        OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
-    let dt_formatted = when.format(&DATE_FORMAT)?;
-    let dt_label = entry::as_no_newlines(dt_formatted).unwrap();
-    let day = Duration::new(/* seconds = */ 60 * 60 * 24, 0);
-    let yesterday = when.checked_sub(day).unwrap();
-    let yesterday_formatted = yesterday.format(&DATE_FORMAT).unwrap();
-    let yesterday_label = entry::as_no_newlines(yesterday_formatted).unwrap();
-
-    let entryname = matches
-        .value_of("entry")
-        .map(|v| v.to_string())
-        .unwrap_or_else(|| {
-            if matches.is_present("yesterday") {
-                yesterday_label.to_string()
-            } else {
-                dt_label.to_string()
-            }
-        });
+                    .value_name("COUNT")
+                    .default_value("10")
+                    .help("the number of changes to list"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("reindex")
+            .about("rebuilds the index of all of the entries in the journal")
+            .long_about(
+                "Commands that look at more than one entry keep an index of the journal in
+.coach/index, so they don't have to read every entry every time. The index is
+updated automatically as entries change, but if it ever seems out of date,
+coach reindex will throw it away and build a new one.",
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("archive")
+            .about("moves old entries into compressed archives")
+            .long_about(
+                "coach archive bundles entries from before a date into compressed archives in
+the archive directory of the journal, one for each month or year. Commands
+that read entries, like coach cat, can still read archived entries, but they
+can't be changed until they're moved back out with coach unarchive. To archive
+everything from before 2023, one archive per year, you could use:
 
-    match matches.subcommand() {
-        ("today", Some(args)) => {
-            let source = args.value_of("from_file").map(|v| v.to_string()).or_else(|| {
-                if args.is_present("from_yesterday") {
-                    Some(yesterday_label.to_string())
-                } else {
-                    None
-                }
-            });
+    coach archive --before 2023-01-01 --by year
+",
+            )
+            .arg(
+                Arg::with_name("before")
+                    .long("before")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .required(true)
+                    .help("archive entries from before DATE"),
+            )
+            .arg(
+                Arg::with_name("by")
+                    .long("by")
+                    .takes_value(true)
+                    .value_name("PERIOD")
+                    .possible_values(&["month", "year"])
+                    .default_value("month")
+                    .help("make one archive for each month, or for each year"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("unarchive")
+            .about("moves archived entries back into the journal")
+            .arg(
+                Arg::with_name("PERIOD")
+                    .index(1)
+                    .help("only unarchive the archive for PERIOD, like 2021 or 2021-10"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("config")
+            .about("shows the settings coach is using, and where each came from")
+            .long_about(
+                "coach reads settings from ~/.config/coach/config.toml (or the file named by
+--config or COACH_CONFIG), then from environment variables, then from command
+line flags, with later ones winning. coach config shows the value of each
+setting, and which of those places it came from. The output is in the same
+format as the config file.",
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("log")
+            .about("lists the changes committed to git by --autocommit")
+            .arg(
+                Arg::with_name("count")
+                    .long("count")
+                    .short("n")
+                    .takes_value(true)
+                    .value_name("COUNT")
+                    .default_value("10")
+                    .help("the number of commits to list"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("grep")
+            .about("searches every entry in the journal")
+            .long_about(
+                "Searches every entry in the journal, including archived ones, for lines
+matching PATTERN, a regular expression. Each match is listed with the entry it
+was found in, and which section of the entry it was in, like:
 
-            migrate(source, &entryname)?;
-        }
-        ("cat", Some(_)) => {
-            let entry = files::entry_from_file(&entryname, MAX_ENTRY_SIZE_BYTES)?;
-            print!("{}", entry);
-        }
-        ("observe", Some(args)) => match args.value_of("NAME") {
-            Some(name_str) => {
-                let value_str = args.value_of("VALUE").unwrap();
-                let name = entry::as_observation_name(name_str.to_string()).unwrap();
-                let value = entry::as_no_newlines(value_str.to_string()).unwrap();
-                observe(&entryname, name, value)?;
-            }
-            None => {
-                let entry = files::entry_from_file(&entryname, MAX_ENTRY_SIZE_BYTES)?;
-                for ob in entry.observations {
-                    println!("{}", ob);
-                }
-            }
-        },
-        ("task", Some(args)) => match args.subcommand() {
-            ("new", Some(args)) => {
-                let message = args.value_of("MESSAGE").unwrap();
-                let message = entry::as_no_newlines(message.to_string()).unwrap();
-                new_task(&entryname, message)?;
-            }
-            ("todo", Some(args)) => {
-                let ix_arg = args.value_of("INDEX").unwrap();
-                let ix_arg: usize = ix_arg.parse()?;
-                update_task(&entryname, ix_arg, entry::Task::Todo)?;
-            }
-            ("done", Some(args)) => {
-                let ix_arg = args.value_of("INDEX").unwrap();
-                let ix_arg: usize = ix_arg.parse()?;
-                update_task(&entryname, ix_arg, entry::Task::Done)?;
-            }
-            ("cancel", Some(args)) => {
-                let ix_arg = args.value_of("INDEX").unwrap();
-                let ix_arg: usize = ix_arg.parse()?;
-                update_task(&entryname, ix_arg, entry::Task::Cancelled)?;
-            }
-            ("working", Some(args)) => {
-                let ix_arg = args.value_of("INDEX").unwrap();
-                let ix_arg: usize = ix_arg.parse()?;
-                update_task(&entryname, ix_arg, entry::Task::Working)?;
-            }
-            _ => {
-                let entry = files::entry_from_file(&entryname, MAX_ENTRY_SIZE_BYTES)?;
-                for (ix, t) in entry.tasks.iter().enumerate() {
-                    println!("{}: {}", ix + 1, t)
-                }
-            }
-        },
-        ("event", Some(args)) => {
-            let mut entry = files::entry_from_file(&entryname, MAX_ENTRY_SIZE_BYTES)?;
-            match args.value_of("MESSAGE") {
-                Some(msg) => {
-                    let text = entry::as_no_newlines(msg.to_string()).unwrap();
-                    let event = entry::Event::Moment { when, text };
-                    println!("{}", event);
-                    entry.events.push(event);
-                    files::entry_to_file(&entryname, &entry)?;
-                }
-                None => {
-                    for e in entry.events {
-                        println!("{}", e);
-                    }
-                }
-            }
-        }
-        ("note", Some(args)) => {
-            let mut entry = files::entry_from_file(&entryname, MAX_ENTRY_SIZE_BYTES)?;
-            let text = match args.value_of("message") {
-                Some(msg) => String::from(msg),
-                None => editor::edit_prompt()?,
-            };
-            let text = text.trim_matches('\n');
-            for body in text.split("\n\n") {
-                match entry::as_note(String::from(body)) {
-                    Some(n) => entry.notes.push(n),
-                    None => {
-                        return Err(Box::new(CommandError {
-                            desc: String::from(
-                                "notes must be nonempty and must not look like events or tasks",
-                            ),
-                        }))
-                    }
-                }
-            }
-            files::entry_to_file(&entryname, &entry)?;
-        }
-        ("edit", _) => {
-            editor::launch_editor(&entryname)?;
-        }
-        _ => {
-            let _ = app.print_long_help();
-            println!();
-        }
-    };
+    2021-10-31 task 2: DONE buy candy for trick'r'treaters
 
-    Ok(())
-}
+Tasks, observations and events are matched as they're written in the entry,
+so you can search for DONE tasks, or the time of an event. Notes are matched
+a line at a time.",
+            )
+            .arg(
+                Arg::with_name("PATTERN")
+                    .help("the regular expression to search for")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("ignore_case")
+                    .long("ignore-case")
+                    .short("i")
+                    .takes_value(false)
+                    .help("match upper and lower case letters alike"),
+            )
+            .arg(
+                Arg::with_name("smart_case")
+                    .long("smart-case")
+                    .short("S")
+                    .takes_value(false)
+                    .help("ignore case, unless PATTERN has an upper case letter in it"),
+            )
+            .arg(
+                Arg::with_name("fixed_strings")
+                    .long("fixed-strings")
+                    .short("F")
+                    .takes_value(false)
+                    .help("search for PATTERN as plain text, rather than as a regular expression"),
+            )
+            .arg(
+                Arg::with_name("section")
+                    .long("section")
+                    .short("s")
+                    .takes_value(true)
+                    .value_name("SECTION")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .use_delimiter(true)
+                    .possible_values(&["observations", "tasks", "events", "notes"])
+                    .help("only search SECTION. Can be given more than once, or as a list like tasks,notes"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only search entries from DATE on. DATE can be anything --date accepts"),
+            )
+            .arg(
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only search entries up to and including DATE"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("query")
+            .setting(AppSettings::AllowLeadingHyphen)
+            .about("lists the tasks, events and observations in the journal that match a query")
+            .long_about(
+                "Lists the tasks, events and observations across the whole journal that match
+QUERY. QUERY is a list of terms, all of which have to match:
 
-fn migrate(source: Option<String>, toname: &str) -> Result<(), Box<dyn Error>> {
-    let mut new = entry::Entry {
-        label: entry::as_no_newlines(String::from(toname)).unwrap(),
-        ..entry::Entry::default()
-    };
+    type:task          only tasks. Also event or observation
+    state:todo,done    tasks that are todo or done
+    tag:release        items with #release in their text
+    name:weather       observations named weather
+    text:offsite       items with offsite in their text, ignoring case
+    offsite            the same as text:offsite
+    since:2024-01-01   items in entries from that date on
+    until:yesterday    items in entries up to and including yesterday
 
-    if let Some(fromname) = source {
-        let mut old = files::entry_from_file(&fromname, MAX_ENTRY_SIZE_BYTES)?;
-        let (live, dead): (Vec<entry::Task>, Vec<entry::Task>) =
-            old.tasks.drain(..).partition(|t| t.is_incomplete());
+A comma separated list of values matches any of them, and a term starting with
+- matches everything the term doesn't. Put values with spaces in them in
+double quotes, like 'text:\"lunch with Sam\"'. since and until take any date
+--date does. For example, to find the tasks cancelled in the last quarter of
+2024:
 
-        old.tasks.extend(dead);
-        new.tasks.extend(live);
+    coach query state:cancelled since:2024-10-01 until:2024-12-31",
+            )
+            .arg(
+                Arg::with_name("QUERY")
+                    .help("the terms to match. With none, everything is listed")
+                    .multiple(true),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("report")
+            .about("sums up a week or a month of entries")
+            .long_about(
+                "Sums up the entries for the week (Monday to Sunday) or the month with today in
+it, or the day given by --date, listing the tasks done, cancelled and still
+open, and the events, observations and notes recorded. Tasks that were migrated
+from day to day are listed once, in the state they were last in. To write up
+last week as Markdown:
 
-        files::new_entry_file(&toname, &new)?;
-        files::entry_to_file(&fromname, &old)?;
+    coach --date -7 report --week --markdown",
+            )
+            .arg(
+                Arg::with_name("week")
+                    .long("week")
+                    .takes_value(false)
+                    .required_unless("month")
+                    .help("report on the week"),
+            )
+            .arg(
+                Arg::with_name("month")
+                    .long("month")
+                    .takes_value(false)
+                    .conflicts_with("week")
+                    .help("report on the month"),
+            )
+            .arg(
+                Arg::with_name("markdown")
+                    .long("markdown")
+                    .takes_value(false)
+                    .help("write the report as Markdown, rather than plain text"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("standup")
+            .about("prints what got done yesterday and what's on for today")
+            .long_about(
+                "Prints the tasks done and events logged in the previous entry, the tasks being
+worked on or still to do today, and any tasks cancelled in either, ready to
+paste into a chat. The previous entry is the latest one before today's, so on
+a Monday it's usually Friday's.",
+            )
+            .arg(
+                Arg::with_name("markdown")
+                    .long("markdown")
+                    .takes_value(false)
+                    .help("write the standup as Markdown, rather than plain text"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("diff")
+            .about("shows what changed between two entries")
+            .long_about(
+                "Compares two entries by what's in them, rather than line by line: tasks added,
+removed or changed state, observations added, removed or changed value, and
+new events and notes. FROM and TO can each be a file, or a date written any
+way --date can. Without TO, FROM is compared to the current entry, and with
+neither, the current entry is compared to the latest entry before it:
 
-        println!("from {} ({} migrated)", fromname, new.tasks.len());
-        for task in new.tasks {
-            println!("{}", &task);
-        }
-    } else {
-        files::new_entry_file(&toname, &new)?;
-    }
+    coach diff
+    coach diff friday
+    coach diff 2021-10-31.bak 2021-10-31",
+            )
+            .arg(
+                Arg::with_name("FROM")
+                    .help("the entry to compare from")
+                    .allow_hyphen_values(true)
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("TO")
+                    .help("the entry to compare to")
+                    .allow_hyphen_values(true)
+                    .index(2),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("time")
+            .about("adds up the time spent in interval events")
+            .long_about(
+                "Adds up the time spent in interval events across the journal, which look like
 
-    Ok(())
-}
+    * <2021-10-31 Sun 09:00>--<2021-10-31 Sun 10:30> client call #acme
 
-fn observe(
-    filename: &str,
-    name: entry::ObservationName,
-    value: entry::NoNewlines,
-) -> Result<(), Box<dyn Error>> {
-    let mut entry = files::entry_from_file(filename, MAX_ENTRY_SIZE_BYTES)?;
-    let observation = entry::Observation { name, value };
-    println!("{}", observation);
-    entry.observations.push(observation);
+and lists the total for each day, each week, or each #tag. Intervals that
+overlap each other are pointed out, since they'd be counted twice. To get a
+timesheet of every interval for a client's October:
 
-    files::entry_to_file(filename, &entry)?;
+    coach time --timesheet --match '#acme' --since 2021-10-01 --until 2021-10-31",
+            )
+            .arg(
+                Arg::with_name("by")
+                    .long("by")
+                    .takes_value(true)
+                    .value_name("GROUPING")
+                    .possible_values(&["day", "week", "tag"])
+                    .default_value("day")
+                    .help("total the time for each day, each week starting on Monday, or each tag. An interval with more than one tag counts toward each of them"),
+            )
+            .arg(
+                Arg::with_name("timesheet")
+                    .long("timesheet")
+                    .takes_value(false)
+                    .conflicts_with("by")
+                    .help("list every interval, and the total time, rather than totals"),
+            )
+            .arg(
+                Arg::with_name("match")
+                    .long("match")
+                    .takes_value(true)
+                    .value_name("TEXT")
+                    .help("only count intervals with TEXT in them, ignoring case, like a keyword or #tag"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only count entries from DATE on. DATE can be anything --date accepts"),
+            )
+            .arg(
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only count entries up to and including DATE"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("chart")
+            .about("charts an observation's values across the journal")
+            .long_about(
+                "Collects the values of the observation NAME from every entry in the journal,
+and charts them, along with their minimum, maximum and average. Values are
+read up to the first thing that isn't part of a number, so a value like
+\"12 bugs\" is charted as 12, and values that don't start with a number are
+skipped. For example:
 
-    Ok(())
-}
+    coach chart \"days until launch\" --since 2021-10-01",
+            )
+            .arg(
+                Arg::with_name("NAME")
+                    .help("the name of the observation to chart")
+                    .required(true)
+                    .validator(observation_name_validator),
+            )
+            .arg(
+                Arg::with_name("sparkline")
+                    .long("sparkline")
+                    .takes_value(false)
+                    .help("draw a single line of bars, rather than a line chart"),
+            )
+            .arg(
+                Arg::with_name("ascii")
+                    .long("ascii")
+                    .takes_value(false)
+                    .help("only use ASCII characters, for terminals that can't show box drawing characters"),
+            )
+            .arg(
+                Arg::with_name("height")
+                    .long("height")
+                    .takes_value(true)
+                    .value_name("ROWS")
+                    .default_value("10")
+                    .validator(index_validator)
+                    .help("how many rows tall to draw the line chart"),
+            )
+            .arg(
+                Arg::with_name("width")
+                    .long("width")
+                    .takes_value(true)
+                    .value_name("COLUMNS")
+                    .default_value("60")
+                    .validator(index_validator)
+                    .help("the most values to draw. If there are more than this, values next to each other are averaged together"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only chart entries from DATE on. DATE can be anything --date accepts"),
+            )
+            .arg(
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only chart entries up to and including DATE"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("stats")
+            .about("reports statistics across the journal")
+            .long_about(
+                "Reads every entry in the journal and reports how many entries there are each
+month, how many tasks were created, done and cancelled, how long tasks took
+from TODO to DONE on average, the days with the most events, and how many
+words of notes were written. Tasks are counted once each, however many
+entries they were migrated through. For example:
 
-fn new_task(filename: &str, message: entry::NoNewlines) -> Result<(), Box<dyn Error>> {
-    let mut entry = files::entry_from_file(filename, MAX_ENTRY_SIZE_BYTES)?;
-    let task = entry::Task::Todo(message);
-    println!("{}", &task);
-    entry.tasks.push(task);
-    entry.tasks.sort();
+    coach stats --since 2021-10-01",
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only count entries from DATE on. DATE can be anything --date accepts"),
+            )
+            .arg(
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .allow_hyphen_values(true)
+                    .help("only count entries up to and including DATE"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("completions")
+            .about("prints a shell completion script")
+            .long_about(
+                "Prints a completion script for SHELL. As well as commands and flags, the
+script completes task indexes, observation names, and entry labels for --entry
+and --date by asking coach for them. For bash, add this to your ~/.bashrc:
 
-    files::entry_to_file(filename, &entry)?;
+    source <(coach completions bash)
 
-    Ok(())
+For zsh, save the script as _coach somewhere in your $fpath. For fish, save it
+as ~/.config/fish/completions/coach.fish.",
+            )
+            .arg(
+                Arg::with_name("SHELL")
+                    .help("the shell to complete for")
+                    .required(true)
+                    .possible_values(&completions::SHELLS),
+            ),
+    )
 }
 
-fn update_task<F>(filename: &str, ix_plus_one: usize, updater: F) -> Result<(), Box<dyn Error>>
-where
-    F: FnOnce(entry::NoNewlines) -> entry::Task,
-{
-    if ix_plus_one == 0 {
-        return Err(Box::new(CommandError {
-            desc: String::from("task indexes start at 1"),
-        }));
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut app = app();
+    // This is used by the completion scripts, rather than by people, so
+    // it's left out of the scripts themselves as well as the help.
+    let matches = app
+        .clone()
+        .subcommand(
+            SubCommand::with_name("__complete")
+                .setting(AppSettings::Hidden)
+                .arg(Arg::with_name("WHAT").required(true).possible_values(&[
+                    "tasks",
+                    "observations",
+                    "entries",
+                ])),
+        )
+        .get_matches();
+
+    // Neither of these needs the journal, so they're run before anything
+    // about it is checked, in case that's what needs fixing.
+    if let ("completions", Some(args)) = matches.subcommand() {
+        return completions_command(args, &mut app);
+    }
+    let config = load_config(&matches)?;
+    let format = output_format(&matches);
+    if let ("config", _) = matches.subcommand() {
+        return config_command(&config, format);
     }
 
-    let ix = ix_plus_one - 1;
+    let cx = context(&matches, config, format)?;
 
-    let mut entry = files::entry_from_file(filename, MAX_ENTRY_SIZE_BYTES)?;
-    if ix >= entry.tasks.len() {
-        return Err(Box::new(CommandError {
-            desc: format!("{} is to large, no task found", ix_plus_one),
-        }));
+    match matches.subcommand() {
+        ("today", Some(args)) => today_command(args, &cx),
+        ("cat", Some(_)) => cat_command(&cx),
+        ("observe", Some(args)) => observe_command(args, &cx),
+        ("task", Some(args)) => task_command(args, &cx),
+        ("event", Some(args)) => event_command(args, &cx),
+        ("note", Some(args)) => note_command(args, &cx),
+        ("edit", _) => edit_command(&cx),
+        ("undo", _) => undo_command(&cx),
+        ("reindex", _) => reindex_command(&cx),
+        ("archive", Some(args)) => archive_command(args, &cx),
+        ("unarchive", Some(args)) => unarchive_command(args, &cx),
+        ("log", Some(args)) => log_command(args, &cx),
+        ("history", Some(args)) => history_command(args, &cx),
+        ("grep", Some(args)) => grep_command(args, &cx),
+        ("query", Some(args)) => query_command(args, &cx),
+        ("report", Some(args)) => report_command(args, &cx),
+        ("standup", Some(args)) => standup_command(args, &cx),
+        ("diff", Some(args)) => diff_command(args, &cx),
+        ("time", Some(args)) => time_command(args, &cx),
+        ("chart", Some(args)) => chart_command(args, &cx),
+        ("stats", Some(args)) => stats_command(args, &cx),
+        ("__complete", Some(args)) => complete_command(args, &cx),
+        _ => {
+            let _ = app.print_long_help();
+            println!();
+            Ok(())
+        }
     }
+}
 
-    entry.update_task(ix, updater);
+// Reads the settings from flags, the environment and the config file.
+fn load_config(matches: &clap::ArgMatches) -> Result<config::Config, io::Error> {
+    let flags = [
+        config::Flag {
+            name: "journal_dir",
+            flag: "--journal-dir",
+            value: matches.value_of("journal_dir"),
+        },
+        config::Flag {
+            name: "timezone",
+            flag: "--timezone",
+            value: matches.value_of("timezone"),
+        },
+        config::Flag {
+            name: "day_starts",
+            flag: "--day-starts",
+            value: matches.value_of("day_starts"),
+        },
+        config::Flag {
+            name: "task_order",
+            flag: "--task-order",
+            value: matches.value_of("task_order"),
+        },
+    ];
+    config::load(matches.value_of("config"), &flags)
+}
 
-    println!("{}", entry.tasks[ix]);
+fn output_format(matches: &clap::ArgMatches) -> output::Format {
+    match matches.value_of("output") {
+        Some("json") => output::Format::Json,
+        Some("ndjson") => output::Format::Ndjson,
+        _ => output::Format::Text,
+    }
+}
+
+// Checks the settings that commands working on the journal need, and
+// works out which entry the command is about.
+fn context(
+    matches: &clap::ArgMatches,
+    config: config::Config,
+    format: output::Format,
+) -> Result<Context, Box<dyn Error>> {
+    let timezone = match config.get("timezone") {
+        Some(_) => Some(setting(&config, "timezone", dates::parse_timezone)?),
+        None => None,
+    };
+    let (when, guessed_zone) = current_time(timezone)?;
+    let day_starts = setting(&config, "day_starts", dates::parse_time_of_day)?;
+    let today = dates::entry_date(when, day_starts);
+    let date = match matches.value_of("date") {
+        Some(expr) => parse_date_arg(expr, today)?,
+        None if matches.is_present("yesterday") => today.previous_day().unwrap(),
+        None => today,
+    };
+    let dt_label = date.format(&DATE_FORMAT)?;
+
+    let keys = crypt::Keyring::new(
+        matches.value_of("key_file").map(|v| v.to_string()),
+        matches.is_present("encrypt") || env::var_os("COACH_ENCRYPT").is_some(),
+    );
+
+    let journal_dir = config::expand_home(config.value("journal_dir"));
+    let settings = Settings {
+        history_dir: journal_path(&journal_dir, HISTORY_DIR),
+        index_file: journal_path(&journal_dir, INDEX_FILE),
+        carried_file: journal_path(&journal_dir, CARRIED_FILE),
+        max_size: setting(&config, "max_entry_size", |v| v.parse().ok())?,
+        order: setting(&config, "task_order", |v| match v {
+            "sorted" => Some(entry::TaskOrder::Sorted),
+            "insertion" => Some(entry::TaskOrder::Insertion),
+            "manual" => Some(entry::TaskOrder::Manual),
+            _ => None,
+        })?,
+        editor: config.value("editor").to_string(),
+        journal_dir: journal_dir.clone(),
+    };
+
+    let autocommit = matches.is_present("autocommit") || env::var_os("COACH_AUTOCOMMIT").is_some();
+
+    let label = matches
+        .value_of("entry")
+        .map(|v| v.to_string())
+        .unwrap_or(dt_label);
+    let entryname = match matches.value_of("entry") {
+        Some(filename) => filename.to_string(),
+        None => journal_path(&journal_dir, &label),
+    };
+
+    Ok(Context {
+        settings,
+        config,
+        keys,
+        format,
+        autocommit,
+        entryname,
+        label,
+        date,
+        today,
+        when,
+        guessed_zone,
+    })
+}
+
+fn today_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let yesterday_label = cx.date.previous_day().unwrap().format(&DATE_FORMAT)?;
+    let yesterday_filename = journal_path(&cx.settings.journal_dir, &yesterday_label);
+    // Migrating from yesterday by default only makes sense if
+    // there was an entry yesterday.
+    let from_yesterday = args.is_present("from_yesterday")
+        || (cx.config.value("today.from_yesterday") == "true"
+            && !args.is_present("no_migrate")
+            && std::path::Path::new(&yesterday_filename).exists());
+    let source = args
+        .value_of("from_file")
+        .map(|v| v.to_string())
+        .or_else(|| {
+            if from_yesterday {
+                Some(yesterday_filename.clone())
+            } else {
+                None
+            }
+        });
+    let source = match args.value_of("from_date") {
+        Some(expr) => Some(journal_path(
+            &cx.settings.journal_dir,
+            &parse_date_arg(expr, cx.today)?.format(&DATE_FORMAT)?,
+        )),
+        None => source,
+    };
+
+    let current = journal::entry_date(&cx.label).unwrap_or(cx.date);
+    migrate(
+        source.clone(),
+        &cx.entryname,
+        &cx.label,
+        current,
+        &cx.settings,
+        &cx.keys,
+    )?;
+    let warn_stale: i64 = setting(&cx.config, "today.warn_stale", |v| v.parse().ok())?;
+    if warn_stale > 0 {
+        let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+        let carried = carried::Carried::open(&cx.settings.carried_file)?;
+        let found = carried::stale(&carried, &entry.tasks, current, warn_stale);
+        if !found.is_empty() {
+            eprintln!(
+                "warning: {} carried for more than {} days:",
+                match found.len() {
+                    1 => String::from("1 task has been"),
+                    n => format!("{} tasks have been", n),
+                },
+                warn_stale
+            );
+            for stale in &found {
+                eprintln!("{}", stale_line(stale));
+            }
+        }
+    }
+    if cx.autocommit {
+        match source {
+            Some(fromname) => commit_changes(
+                &cx.settings,
+                &[&cx.entryname, &fromname],
+                &format!("today: from {}", fromname),
+            )?,
+            None => commit_changes(&cx.settings, &[&cx.entryname], "today")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn cat_command(cx: &Context) -> Result<(), Box<dyn Error>> {
+    let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+    match cx.format {
+        output::Format::Text => print!("{}", entry),
+        _ => output::write_record(&mut io::stdout(), &output::entry_record(&entry))?,
+    }
+
+    Ok(())
+}
+
+fn observe_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    match (args.value_of("rm"), args.value_of("NAME")) {
+        (None, Some(name_str)) if args.is_present("set") => {
+            let value_str = args.value_of("VALUE").unwrap();
+            let name = entry::as_observation_name(name_str.to_string()).unwrap();
+            let value = entry::as_no_newlines(value_str.to_string()).unwrap();
+            let mut entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            entry.set_observation(name, value);
+            println!("{}: {}", name_str, value_str);
+            let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+            files::entry_to_file(&cx.entryname, &entry, &cx.keys)?;
+            save_history(&cx.settings, snapshot)?;
+            if cx.autocommit {
+                let message = format!("observe --set: {}: {}", name_str, value_str);
+                commit_changes(&cx.settings, &[&cx.entryname], &message)?;
+            }
+        }
+        (Some(name_str), _) => {
+            let name = entry::as_observation_name(name_str.to_string()).unwrap();
+            let mut entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            if entry.remove_observation(&name) == 0 {
+                return Err(Box::new(CommandError {
+                    desc: format!("there is no observation named {}", name_str),
+                }));
+            }
+            let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+            files::entry_to_file(&cx.entryname, &entry, &cx.keys)?;
+            save_history(&cx.settings, snapshot)?;
+            if cx.autocommit {
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("observe --rm: {}", name_str),
+                )?;
+            }
+        }
+        (_, Some(name_str)) => {
+            let value_str = args.value_of("VALUE").unwrap();
+            let name = entry::as_observation_name(name_str.to_string()).unwrap();
+            let value = entry::as_no_newlines(value_str.to_string()).unwrap();
+            observe(&cx.entryname, name, value, &cx.settings, &cx.keys)?;
+            if cx.autocommit {
+                let message = format!("observe: {}: {}", name_str, value_str);
+                commit_changes(&cx.settings, &[&cx.entryname], &message)?;
+            }
+        }
+        (_, None) => match args.value_of("history") {
+            Some(name_str) => {
+                let (entries, problems) = journal::read_entries(
+                    &cx.settings.journal_dir,
+                    &cx.settings.index_file,
+                    cx.settings.max_size,
+                    &cx.keys,
+                )?;
+                print_problems(&problems);
+                let mut records = vec![];
+                for journal_entry in entries.iter() {
+                    for ob in journal_entry.entry.observations.iter() {
+                        if ob.name.to_string() != name_str {
+                            continue;
+                        }
+                        match cx.format {
+                            output::Format::Text => {
+                                println!("{} {}", journal_entry.name, ob.value)
+                            }
+                            _ => {
+                                records.push(output::observation_history_record(journal_entry, ob))
+                            }
+                        }
+                    }
+                }
+                print_records(cx.format, &records)?;
+            }
+            None => {
+                let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+                match cx.format {
+                    output::Format::Text => {
+                        for ob in entry.observations {
+                            println!("{}", ob);
+                        }
+                    }
+                    _ => {
+                        let records: Vec<_> = entry
+                            .observations
+                            .iter()
+                            .map(output::observation_record)
+                            .collect();
+                        print_records(cx.format, &records)?;
+                    }
+                }
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn task_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    match args.subcommand() {
+        ("new", Some(args)) => {
+            let message = args.value_of("MESSAGE").unwrap();
+            let message = entry::as_no_newlines(message.to_string()).unwrap();
+            let text = message.to_string();
+            new_task(&cx.entryname, message, &cx.settings, &cx.keys)?;
+            if cx.autocommit {
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("task new: {}", text),
+                )?;
+            }
+        }
+        ("todo", Some(args)) => {
+            let selection = task_selection(args);
+            let texts = update_tasks(
+                &cx.entryname,
+                &selection,
+                entry::Task::Todo,
+                &cx.settings,
+                &cx.keys,
+            )?;
+            if cx.autocommit {
+                let message = format!("task todo: {}", texts.join(", "));
+                commit_changes(&cx.settings, &[&cx.entryname], &message)?;
+            }
+        }
+        ("done", Some(args)) => {
+            let selection = task_selection(args);
+            let texts = update_tasks(
+                &cx.entryname,
+                &selection,
+                entry::Task::Done,
+                &cx.settings,
+                &cx.keys,
+            )?;
+            if cx.autocommit {
+                let message = format!("task done: {}", texts.join(", "));
+                commit_changes(&cx.settings, &[&cx.entryname], &message)?;
+            }
+        }
+        ("cancel", Some(args)) => {
+            let selection = task_selection(args);
+            let texts = update_tasks(
+                &cx.entryname,
+                &selection,
+                entry::Task::Cancelled,
+                &cx.settings,
+                &cx.keys,
+            )?;
+            if cx.autocommit {
+                let message = format!("task cancel: {}", texts.join(", "));
+                commit_changes(&cx.settings, &[&cx.entryname], &message)?;
+            }
+        }
+        ("working", Some(args)) => {
+            let selection = task_selection(args);
+            let texts = update_tasks(
+                &cx.entryname,
+                &selection,
+                entry::Task::Working,
+                &cx.settings,
+                &cx.keys,
+            )?;
+            if cx.autocommit {
+                let message = format!("task working: {}", texts.join(", "));
+                commit_changes(&cx.settings, &[&cx.entryname], &message)?;
+            }
+        }
+        ("edit", Some(args)) => {
+            let selection = task_selection(args);
+            let message = args.value_of("MESSAGE").unwrap();
+            let message = entry::as_no_newlines(message.to_string()).unwrap();
+            let text = edit_task(&cx.entryname, &selection, message, &cx.settings, &cx.keys)?;
+            if cx.autocommit {
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("task edit: {}", text),
+                )?;
+            }
+        }
+        ("rm", Some(args)) => {
+            let selection = task_selection(args);
+            let text = remove_task(&cx.entryname, &selection, &cx.settings, &cx.keys)?;
+            if cx.autocommit {
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("task rm: {}", text),
+                )?;
+            }
+        }
+        ("mv", Some(args)) => {
+            let selection = task_selection(args);
+            let position: usize = args.value_of("POSITION").unwrap().parse()?;
+            let text = move_task(&cx.entryname, &selection, position, &cx.settings, &cx.keys)?;
+            if cx.autocommit {
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("task mv: {}", text),
+                )?;
+            }
+        }
+        _ if args.is_present("stale") => {
+            let days: i64 = args.value_of("stale").unwrap().parse()?;
+            let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            let carried = carried::Carried::open(&cx.settings.carried_file)?;
+            let current = journal::entry_date(&cx.label).unwrap_or(cx.date);
+            let found = carried::stale(&carried, &entry.tasks, current, days);
+            match cx.format {
+                output::Format::Text => {
+                    for stale in &found {
+                        println!("{}", stale_line(stale));
+                    }
+                }
+                _ => {
+                    let records: Vec<_> = found.iter().map(output::stale_record).collect();
+                    print_records(cx.format, &records)?;
+                }
+            }
+        }
+        _ => {
+            let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            match cx.format {
+                output::Format::Text => {
+                    for (ix, t) in entry.tasks.iter().enumerate() {
+                        println!("{}: {}", ix + 1, t)
+                    }
+                }
+                _ => {
+                    let records: Vec<_> = entry
+                        .tasks
+                        .iter()
+                        .enumerate()
+                        .map(|(ix, t)| output::task_record(ix + 1, t))
+                        .collect();
+                    print_records(cx.format, &records)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn event_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let mut entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+    match args.value_of("MESSAGE") {
+        Some(msg) => {
+            if cx.guessed_zone {
+                eprintln!("warning: couldn't find the local time zone, so the event's time is in UTC. You can set one with --timezone");
+            }
+            let text = entry::as_no_newlines(msg.to_string()).unwrap();
+            // Entries are written to the minute, in the configured
+            // time zone.
+            let minute = time::Time::from_hms(cx.when.hour(), cx.when.minute(), 0)?;
+            let event = entry::Event::Moment {
+                when: time::PrimitiveDateTime::new(cx.when.date(), minute),
+                text,
+            };
+            println!("{}", event);
+            entry.events.push(event);
+            let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+            files::entry_to_file(&cx.entryname, &entry, &cx.keys)?;
+            save_history(&cx.settings, snapshot)?;
+            if cx.autocommit {
+                commit_changes(&cx.settings, &[&cx.entryname], &format!("event: {}", msg))?;
+            }
+        }
+        None => match cx.format {
+            output::Format::Text => {
+                for e in entry.events {
+                    println!("{}", e);
+                }
+            }
+            _ => {
+                let records: Vec<_> = entry.events.iter().map(output::event_record).collect();
+                print_records(cx.format, &records)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn note_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    match args.subcommand() {
+        ("list", Some(_)) => {
+            let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            match cx.format {
+                output::Format::Text => {
+                    for (ix, n) in entry.notes.iter().enumerate() {
+                        let text = n.to_string();
+                        println!("{}: {}", ix + 1, text.lines().next().unwrap_or_default());
+                    }
+                }
+                _ => {
+                    let records: Vec<_> = entry
+                        .notes
+                        .iter()
+                        .enumerate()
+                        .map(|(ix, n)| output::note_record(ix + 1, n))
+                        .collect();
+                    print_records(cx.format, &records)?;
+                }
+            }
+        }
+        ("edit", Some(args)) => {
+            let ix_arg: usize = args.value_of("INDEX").unwrap().parse()?;
+            let mut entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            let ix = item_index(entry.notes.len(), ix_arg, "note")?;
+            let text = editor::edit_text(&cx.settings.editor, &entry.notes[ix].to_string())?;
+            let text = text.trim_matches('\n');
+            entry.notes[ix] = entry::as_note(String::from(text)).ok_or_else(|| CommandError {
+                desc: String::from(
+                    "notes must be nonempty, must not contain blank lines, and must not \
+                             look like events or tasks. The note wasn't changed",
+                ),
+            })?;
+            let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+            files::entry_to_file(&cx.entryname, &entry, &cx.keys)?;
+            save_history(&cx.settings, snapshot)?;
+            if cx.autocommit {
+                let first_line = text.lines().next().unwrap_or_default();
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("note edit: {}", first_line),
+                )?;
+            }
+        }
+        ("rm", Some(args)) => {
+            let ix_arg: usize = args.value_of("INDEX").unwrap().parse()?;
+            let mut entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            let ix = item_index(entry.notes.len(), ix_arg, "note")?;
+            let text = entry.notes.remove(ix).to_string();
+            let first_line = text.lines().next().unwrap_or_default();
+            println!("removed: {}", first_line);
+            let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+            files::entry_to_file(&cx.entryname, &entry, &cx.keys)?;
+            save_history(&cx.settings, snapshot)?;
+            if cx.autocommit {
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("note rm: {}", first_line),
+                )?;
+            }
+        }
+        _ => {
+            let mut entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+            let text = match args.value_of("message") {
+                Some(msg) => Zeroizing::new(String::from(msg)),
+                None => editor::edit_prompt(&cx.settings.editor)?,
+            };
+            let text = text.trim_matches('\n');
+            for body in text.split("\n\n") {
+                match entry::as_note(String::from(body)) {
+                    Some(n) => entry.notes.push(n),
+                    None => {
+                        return Err(Box::new(CommandError {
+                            desc: String::from(
+                                "notes must be nonempty and must not look like events or tasks",
+                            ),
+                        }))
+                    }
+                }
+            }
+            let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+            files::entry_to_file(&cx.entryname, &entry, &cx.keys)?;
+            save_history(&cx.settings, snapshot)?;
+            if cx.autocommit {
+                let first_line = text.lines().next().unwrap_or_default();
+                commit_changes(
+                    &cx.settings,
+                    &[&cx.entryname],
+                    &format!("note: {}", first_line),
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn edit_command(cx: &Context) -> Result<(), Box<dyn Error>> {
+    let snapshot = snapshot_history(&cx.settings, &[&cx.entryname])?;
+    if files::is_encrypted_file(&cx.entryname)? {
+        let text = files::text_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+        let text = editor::edit_text(&cx.settings.editor, &text)?;
+        files::text_to_file(&cx.entryname, &text, &cx.keys)?;
+    } else {
+        editor::launch_editor(&cx.settings.editor, &cx.entryname)?;
+    }
+    save_history(&cx.settings, snapshot)?;
+    if cx.autocommit {
+        commit_changes(
+            &cx.settings,
+            &[&cx.entryname],
+            &format!("edit: {}", cx.entryname),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn undo_command(cx: &Context) -> Result<(), Box<dyn Error>> {
+    match history::undo(&cx.settings.history_dir)? {
+        Some(change) => {
+            println!("undid: {}", change.command);
+            if cx.autocommit {
+                let filenames: Vec<&str> = change.filenames.iter().map(|f| f.as_str()).collect();
+                commit_changes(
+                    &cx.settings,
+                    &filenames,
+                    &format!("undo: {}", change.command),
+                )?;
+            }
+        }
+        None => println!("nothing to undo"),
+    }
+
+    Ok(())
+}
+
+fn reindex_command(cx: &Context) -> Result<(), Box<dyn Error>> {
+    let (count, problems) = journal::reindex(
+        &cx.settings.journal_dir,
+        &cx.settings.index_file,
+        cx.settings.max_size,
+        &cx.keys,
+    )?;
+    print_problems(&problems);
+    println!("indexed {} entries", count);
+
+    Ok(())
+}
+
+fn archive_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let before = parse_date_arg(args.value_of("before").unwrap(), cx.today)?;
+    let period = match args.value_of("by") {
+        Some("year") => archive::Period::Year,
+        _ => archive::Period::Month,
+    };
+    let bundles = archive::plan_archive(&cx.settings.journal_dir, before, period)?;
+    let filenames = bundle_filenames(&cx.settings, &bundles);
+    let filenames: Vec<&str> = filenames.iter().map(|f| f.as_str()).collect();
+    let snapshot = snapshot_history(&cx.settings, &filenames)?;
+    archive::archive(
+        &cx.settings.journal_dir,
+        &bundles,
+        cx.settings.max_size + crypt::OVERHEAD,
+    )?;
+    save_history(&cx.settings, snapshot)?;
+    for bundle in bundles.iter() {
+        println!(
+            "archived {} entries in {}",
+            bundle.names.len(),
+            bundle.archive
+        );
+    }
+    if cx.autocommit && !bundles.is_empty() {
+        commit_changes(
+            &cx.settings,
+            &filenames,
+            &format!("archive: before {}", before.format(&DATE_FORMAT)?),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn unarchive_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let bundles = archive::plan_unarchive(
+        &cx.settings.journal_dir,
+        args.value_of("PERIOD"),
+        cx.settings.max_size + crypt::OVERHEAD,
+    )?;
+    let filenames = bundle_filenames(&cx.settings, &bundles);
+    let filenames: Vec<&str> = filenames.iter().map(|f| f.as_str()).collect();
+    let snapshot = snapshot_history(&cx.settings, &filenames)?;
+    archive::unarchive(
+        &cx.settings.journal_dir,
+        &bundles,
+        cx.settings.max_size + crypt::OVERHEAD,
+    )?;
+    save_history(&cx.settings, snapshot)?;
+    for bundle in bundles.iter() {
+        println!(
+            "unarchived {} entries from {}",
+            bundle.names.len(),
+            bundle.archive
+        );
+    }
+    if cx.autocommit && !bundles.is_empty() {
+        commit_changes(&cx.settings, &filenames, "unarchive")?;
+    }
 
-    entry.tasks.sort();
-    files::entry_to_file(filename, &entry)?;
+    Ok(())
+}
+
+fn config_command(config: &config::Config, format: output::Format) -> Result<(), Box<dyn Error>> {
+    match format {
+        output::Format::Text => {
+            match &config.filename {
+                Some(filename) => println!("# read from {}", filename),
+                None => println!("# no config file found"),
+            }
+            for s in config.settings() {
+                match &s.value {
+                    Some(value) => println!("{} = {:?} # from {}", s.name, value, s.source),
+                    None => println!("# {} is not set", s.name),
+                }
+            }
+        }
+        _ => {
+            let records: Vec<_> = config
+                .settings()
+                .iter()
+                .map(output::setting_record)
+                .collect();
+            print_records(format, &records)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn log_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let count: usize = args.value_of("count").unwrap().parse()?;
+    for line in git::log(&cx.settings.journal_dir, count)? {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn history_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let count: usize = args.value_of("count").unwrap().parse()?;
+    let changes = history::changes(&cx.settings.history_dir)?;
+    let changes = &changes[..count.min(changes.len())];
+    match cx.format {
+        output::Format::Text => {
+            for change in changes {
+                let changed_at = change.when.to_offset(cx.when.offset());
+                println!(
+                    "{} {}",
+                    changed_at.format(&HISTORY_TIME_FORMAT)?,
+                    change.command
+                );
+            }
+        }
+        _ => {
+            let records: Vec<_> = changes
+                .iter()
+                .map(|change| output::change_record(change, cx.when.offset()))
+                .collect();
+            print_records(cx.format, &records)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn grep_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let pattern = args.value_of("PATTERN").unwrap();
+    let ignore_case = args.is_present("ignore_case")
+        || (args.is_present("smart_case") && !pattern.chars().any(char::is_uppercase));
+    let pattern = if args.is_present("fixed_strings") {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    let regex = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| CommandError {
+            desc: format!("{}", e),
+        })?;
+    let sections = match args.values_of("section") {
+        Some(names) => names
+            .map(|name| search::Section::from_name(name).unwrap())
+            .collect(),
+        None => search::SECTIONS.to_vec(),
+    };
+    let entries = read_entries_between(args, cx.today, &cx.settings, &cx.keys)?;
+    let mut records = vec![];
+    for journal_entry in entries.iter() {
+        for found in search::search(&journal_entry.entry, &regex, &sections) {
+            match cx.format {
+                output::Format::Text => println!(
+                    "{} {} {}: {}",
+                    journal_entry.name, found.section, found.index, found.line
+                ),
+                _ => records.push(output::match_record(journal_entry, &found)),
+            }
+        }
+    }
+    print_records(cx.format, &records)?;
+
+    Ok(())
+}
+
+fn query_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let terms: Vec<&str> = args.values_of("QUERY").unwrap_or_default().collect();
+    let parsed = query::parse(&terms.join(" "), cx.today).map_err(|e| CommandError {
+        desc: format!("{}", e),
+    })?;
+    let (entries, problems) = journal::read_entries(
+        &cx.settings.journal_dir,
+        &cx.settings.index_file,
+        cx.settings.max_size,
+        &cx.keys,
+    )?;
+    print_problems(&problems);
+    let found = parsed.run(&entries);
+    match cx.format {
+        output::Format::Text if !found.is_empty() => {
+            let rows: Vec<_> = found
+                .iter()
+                .map(|f| {
+                    vec![
+                        f.journal_entry.name.clone(),
+                        f.item.kind().to_string(),
+                        f.index.to_string(),
+                        f.item.to_string(),
+                    ]
+                })
+                .collect();
+            output::write_table(
+                &mut io::stdout(),
+                &["ENTRY", "TYPE", "INDEX", "ITEM"],
+                &rows,
+            )?;
+        }
+        output::Format::Text => {}
+        _ => {
+            let records: Vec<_> = found.iter().map(output::found_record).collect();
+            print_records(cx.format, &records)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn report_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let period = if args.is_present("month") {
+        report::Period::Month
+    } else {
+        report::Period::Week
+    };
+    let (first, last) = report::period_dates(period, cx.date);
+    let (journal_entries, problems) = journal::read_entries(
+        &cx.settings.journal_dir,
+        &cx.settings.index_file,
+        cx.settings.max_size,
+        &cx.keys,
+    )?;
+    print_problems(&problems);
+    let entries: Vec<(String, entry::Entry)> = journal_entries
+        .into_iter()
+        .filter(|e| e.date >= first && e.date <= last)
+        .map(|e| (e.name, e.entry))
+        .collect();
+    let summary = report::build(&entries);
+    report::write(
+        &mut io::stdout(),
+        &summary,
+        first,
+        last,
+        args.is_present("markdown"),
+    )?;
 
     Ok(())
 }
+
+fn standup_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let entry = files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys)?;
+    let current = journal::entry_date(&cx.label).unwrap_or(cx.date);
+    let previous = match journal::previous_entry_name(&cx.settings.journal_dir, current)? {
+        Some(name) => {
+            let previous_entry = files::entry_from_file(
+                &journal_path(&cx.settings.journal_dir, &name),
+                cx.settings.max_size,
+                &cx.keys,
+            )?;
+            Some((name, previous_entry))
+        }
+        None => None,
+    };
+    let summary = standup::build(
+        previous.as_ref().map(|(name, e)| (name.as_str(), e)),
+        &entry,
+    );
+    standup::write(&mut io::stdout(), &summary, args.is_present("markdown"))?;
+
+    Ok(())
+}
+
+fn diff_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    // A file, if there is one by that name, or else the entry for
+    // a date.
+    let entry_file = |arg: &str| -> Result<String, CommandError> {
+        if std::path::Path::new(arg).exists() {
+            return Ok(arg.to_string());
+        }
+        match dates::parse_date(arg, cx.today) {
+            Some(date) => Ok(journal_path(
+                &cx.settings.journal_dir,
+                &date.format(&DATE_FORMAT).unwrap(),
+            )),
+            None => Err(CommandError {
+                desc: format!("there is no file or date called {}", arg),
+            }),
+        }
+    };
+    let to_name = match args.value_of("TO") {
+        Some(arg) => entry_file(arg)?,
+        None => cx.entryname.clone(),
+    };
+    let from_name = match args.value_of("FROM") {
+        Some(arg) => entry_file(arg)?,
+        None => {
+            let current = journal::entry_date(&cx.label).unwrap_or(cx.date);
+            match journal::previous_entry_name(&cx.settings.journal_dir, current)? {
+                Some(name) => journal_path(&cx.settings.journal_dir, &name),
+                None => {
+                    return Err(Box::new(CommandError {
+                        desc: String::from("there is no earlier entry to compare to"),
+                    }))
+                }
+            }
+        }
+    };
+    let from = files::entry_from_file(&from_name, cx.settings.max_size, &cx.keys)?;
+    let to = files::entry_from_file(&to_name, cx.settings.max_size, &cx.keys)?;
+    let changes = diff::diff(&from, &to);
+    match cx.format {
+        output::Format::Text => {
+            println!("--- {}", from_name);
+            println!("+++ {}", to_name);
+            if !changes.is_empty() {
+                println!();
+            }
+            diff::write(&mut io::stdout(), &changes)?;
+        }
+        _ => {
+            let records: Vec<_> = changes.iter().map(output::diff_record).collect();
+            print_records(cx.format, &records)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn time_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let entries = read_entries_between(args, cx.today, &cx.settings, &cx.keys)?;
+    let mut intervals = timesheet::intervals(&entries);
+    if let Some(text) = args.value_of("match") {
+        let text = text.to_lowercase();
+        intervals.retain(|i| i.text.to_lowercase().contains(&text));
+    }
+
+    let clock = |i: &timesheet::Interval| -> Result<String, time::error::Format> {
+        Ok(format!(
+            "{} {}-{} {}",
+            i.journal_entry.name,
+            i.begin.format(&CLOCK_FORMAT)?,
+            i.end.format(&CLOCK_FORMAT)?,
+            i.text
+        ))
+    };
+    for (a, b) in timesheet::overlaps(&intervals) {
+        eprintln!(
+            "warning: {} overlaps {}",
+            clock(&intervals[a])?,
+            clock(&intervals[b])?
+        );
+    }
+
+    let total = intervals
+        .iter()
+        .fold(time::Duration::ZERO, |sum, i| sum + i.duration());
+    if args.is_present("timesheet") {
+        match cx.format {
+            output::Format::Text => {
+                let mut rows = vec![];
+                for i in intervals.iter() {
+                    rows.push(vec![
+                        i.journal_entry.name.clone(),
+                        i.begin.format(&CLOCK_FORMAT)?,
+                        i.end.format(&CLOCK_FORMAT)?,
+                        timesheet::format_duration(i.duration()),
+                        timesheet::format_hours(i.duration()),
+                        i.text.clone(),
+                    ]);
+                }
+                rows.push(vec![
+                    String::from("total"),
+                    String::new(),
+                    String::new(),
+                    timesheet::format_duration(total),
+                    timesheet::format_hours(total),
+                    String::new(),
+                ]);
+                output::write_table(
+                    &mut io::stdout(),
+                    &["ENTRY", "BEGIN", "END", "TIME", "HOURS", "TEXT"],
+                    &rows,
+                )?;
+            }
+            _ => {
+                let records: Vec<_> = intervals.iter().map(output::interval_record).collect();
+                print_records(cx.format, &records)?;
+            }
+        }
+    } else {
+        let (grouping, heading) = match args.value_of("by") {
+            Some("week") => (timesheet::Grouping::Week, "WEEK OF"),
+            Some("tag") => (timesheet::Grouping::Tag, "TAG"),
+            _ => (timesheet::Grouping::Day, "DAY"),
+        };
+        let totals = timesheet::totals(&intervals, grouping);
+        match cx.format {
+            output::Format::Text => {
+                let mut rows: Vec<_> = totals
+                    .iter()
+                    .map(|t| {
+                        vec![
+                            t.name.clone(),
+                            timesheet::format_duration(t.duration),
+                            timesheet::format_hours(t.duration),
+                            t.intervals.to_string(),
+                        ]
+                    })
+                    .collect();
+                rows.push(vec![
+                    String::from("total"),
+                    timesheet::format_duration(total),
+                    timesheet::format_hours(total),
+                    intervals.len().to_string(),
+                ]);
+                output::write_table(
+                    &mut io::stdout(),
+                    &[heading, "TIME", "HOURS", "INTERVALS"],
+                    &rows,
+                )?;
+            }
+            _ => {
+                let records: Vec<_> = totals.iter().map(output::total_record).collect();
+                print_records(cx.format, &records)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn chart_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let name = args.value_of("NAME").unwrap();
+    let entries = read_entries_between(args, cx.today, &cx.settings, &cx.keys)?;
+    let mut points = vec![];
+    let mut skipped = 0;
+    for journal_entry in entries.iter() {
+        for ob in journal_entry.entry.observations.iter() {
+            if ob.name.to_string() != name {
+                continue;
+            }
+            match chart::parse_number(&ob.value.to_string()) {
+                Some(value) => points.push((journal_entry.name.as_str(), value)),
+                None => skipped += 1,
+            }
+        }
+    }
+    if skipped > 0 {
+        eprintln!(
+            "warning: skipping {} values of {} that aren't numbers",
+            skipped, name
+        );
+    }
+    let values: Vec<f64> = points.iter().map(|(_, value)| *value).collect();
+    let stats = match chart::stats(&values) {
+        Some(stats) => stats,
+        None => {
+            return Err(Box::new(CommandError {
+                desc: format!("there are no numbers recorded for {}", name),
+            }))
+        }
+    };
+    let (first, _) = points[0];
+    let (last, latest) = points[points.len() - 1];
+
+    let ascii = args.is_present("ascii");
+    let width: usize = args.value_of("width").unwrap().parse()?;
+    let drawn = chart::downsample(&values, width);
+    if args.is_present("sparkline") {
+        println!("{} {}", name, chart::sparkline(&drawn, ascii));
+    } else {
+        let height: usize = args.value_of("height").unwrap().parse()?;
+        println!("{}", name);
+        for line in chart::line_chart(&drawn, height, ascii, first, last) {
+            println!("{}", line);
+        }
+    }
+    let when = |value: f64| {
+        let (entry_name, _) = points.iter().find(|(_, v)| *v == value).unwrap();
+        format!("{} ({})", chart::format_number(value), entry_name)
+    };
+    println!(
+        "min {}  max {}  average {}  latest {} ({})  from {} values",
+        when(stats.min),
+        when(stats.max),
+        chart::format_number(stats.average),
+        chart::format_number(latest),
+        last,
+        values.len()
+    );
+
+    Ok(())
+}
+
+fn stats_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    let entries = read_entries_between(args, cx.today, &cx.settings, &cx.keys)?;
+    if entries.is_empty() {
+        return Err(Box::new(CommandError {
+            desc: String::from("there are no entries to count"),
+        }));
+    }
+    let carried = carried::Carried::open(&cx.settings.carried_file)?;
+    let summary = stats::build(&entries, &carried);
+    match cx.format {
+        output::Format::Text => {
+            let first = &entries[0].name;
+            let last = &entries[entries.len() - 1].name;
+            stats::write(&mut io::stdout(), &summary, first, last)?;
+        }
+        _ => output::write_record(&mut io::stdout(), &output::stats_record(&summary))?,
+    }
+
+    Ok(())
+}
+
+fn completions_command(args: &clap::ArgMatches, app: &mut App) -> Result<(), Box<dyn Error>> {
+    let shell_name = args.value_of("SHELL").unwrap();
+    let mut generated = vec![];
+    app.gen_completions_to("coach", shell_name.parse()?, &mut generated);
+    let generated = String::from_utf8(generated)?;
+    print!("{}", completions::add_dynamic(shell_name, &generated));
+
+    Ok(())
+}
+
+fn complete_command(args: &clap::ArgMatches, cx: &Context) -> Result<(), Box<dyn Error>> {
+    // Completing shouldn't ever stop to ask for a passphrase, so
+    // encrypted entries are skipped unless there's a key file.
+    let readable = |filename: &str| {
+        cx.keys.has_key_file() || !files::is_encrypted_file(filename).unwrap_or(true)
+    };
+    let candidates = match args.value_of("WHAT").unwrap() {
+        "tasks" if readable(&cx.entryname) => {
+            match files::entry_from_file(&cx.entryname, cx.settings.max_size, &cx.keys) {
+                Ok(entry) => completions::task_candidates(&entry.tasks),
+                Err(_) => vec![],
+            }
+        }
+        "observations" => {
+            let names = journal::entry_names(&cx.settings.journal_dir).unwrap_or_default();
+            let recent = &names[names.len().saturating_sub(completions::RECENT_ENTRIES)..];
+            let entries: Vec<_> = recent
+                .iter()
+                .map(|name| journal_path(&cx.settings.journal_dir, name))
+                .filter(|filename| readable(filename))
+                .filter_map(|filename| {
+                    files::entry_from_file(&filename, cx.settings.max_size, &cx.keys).ok()
+                })
+                .collect();
+            completions::observation_names(&entries)
+        }
+        "entries" => journal::entry_names(&cx.settings.journal_dir).unwrap_or_default(),
+        _ => vec![],
+    };
+    for candidate in candidates {
+        println!("{}", candidate);
+    }
+
+    Ok(())
+}
+
+// date is the new entry's date. The migrated tasks are noted as seen on
+// the day of the entry they came from, so coach task --stale can tell how
+// long they've been carried.
+fn migrate(
+    source: Option<String>,
+    toname: &str,
+    label: &str,
+    date: time::Date,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<(), Box<dyn Error>> {
+    let mut new = entry::Entry {
+        label: entry::as_no_newlines(String::from(label)).unwrap(),
+        ..entry::Entry::default()
+    };
+
+    if let Some(fromname) = source {
+        let mut old = files::entry_from_file(&fromname, settings.max_size, keys)?;
+        let (live, dead): (Vec<entry::Task>, Vec<entry::Task>) =
+            old.tasks.drain(..).partition(|t| t.is_incomplete());
+
+        let mut carried = carried::Carried::open(&settings.carried_file)?;
+        let from_date = journal::entry_date(&old.label.to_string()).unwrap_or(date);
+        carried.migrated(&fromname, &live, &dead, from_date, keys);
+
+        old.tasks.extend(dead);
+        new.tasks.extend(live);
+        old.order_tasks(settings.order);
+        new.order_tasks(settings.order);
+
+        // Once the new entry has been written, the change is recorded even
+        // if updating the old one fails, so it can still be undone.
+        let snapshot = snapshot_history(settings, &[toname, &fromname])?;
+        files::new_entry_file(toname, &new, keys)?;
+        let written = files::entry_to_file(&fromname, &old, keys);
+        save_history(settings, snapshot)?;
+        written?;
+        carried.save()?;
+
+        println!("from {} ({} migrated)", fromname, new.tasks.len());
+        for task in new.tasks {
+            println!("{}", &task);
+        }
+    } else {
+        let snapshot = snapshot_history(settings, &[toname])?;
+        files::new_entry_file(toname, &new, keys)?;
+        save_history(settings, snapshot)?;
+    }
+
+    Ok(())
+}
+
+fn observe(
+    filename: &str,
+    name: entry::ObservationName,
+    value: entry::NoNewlines,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<(), Box<dyn Error>> {
+    let mut entry = files::entry_from_file(filename, settings.max_size, keys)?;
+    let observation = entry::Observation { name, value };
+    println!("{}", observation);
+    entry.observations.push(observation);
+
+    let snapshot = snapshot_history(settings, &[filename])?;
+    files::entry_to_file(filename, &entry, keys)?;
+    save_history(settings, snapshot)?;
+
+    Ok(())
+}
+
+fn new_task(
+    filename: &str,
+    message: entry::NoNewlines,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<(), Box<dyn Error>> {
+    let mut entry = files::entry_from_file(filename, settings.max_size, keys)?;
+    let task = entry::Task::Todo(message);
+    println!("{}", &task);
+    entry.tasks.push(task);
+    entry.order_tasks(settings.order);
+
+    let snapshot = snapshot_history(settings, &[filename])?;
+    files::entry_to_file(filename, &entry, keys)?;
+    save_history(settings, snapshot)?;
+
+    Ok(())
+}
+
+// Applies updater to every selected task, all in one change. Returns the
+// messages of the updated tasks.
+fn update_tasks<F>(
+    filename: &str,
+    selection: &tasks::Selection,
+    updater: F,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<Vec<String>, Box<dyn Error>>
+where
+    F: Fn(entry::NoNewlines) -> entry::Task,
+{
+    let mut entry = files::entry_from_file(filename, settings.max_size, keys)?;
+    let indexes = select_tasks(&entry, selection)?;
+
+    let mut texts = vec![];
+    for ix in indexes {
+        entry.update_task(ix, &updater);
+        println!("{}", entry.tasks[ix]);
+        texts.push(entry.tasks[ix].text().to_string());
+    }
+
+    entry.order_tasks(settings.order);
+    let snapshot = snapshot_history(settings, &[filename])?;
+    files::entry_to_file(filename, &entry, keys)?;
+    save_history(settings, snapshot)?;
+
+    Ok(texts)
+}
+
+// Returns the message of the edited task.
+fn edit_task(
+    filename: &str,
+    selection: &tasks::Selection,
+    message: entry::NoNewlines,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<String, Box<dyn Error>> {
+    let mut entry = files::entry_from_file(filename, settings.max_size, keys)?;
+    let ix = select_task(&entry, selection)?;
+
+    entry.edit_task(ix, message);
+
+    println!("{}", entry.tasks[ix]);
+    let text = entry.tasks[ix].message().to_string();
+
+    entry.order_tasks(settings.order);
+    let snapshot = snapshot_history(settings, &[filename])?;
+    files::entry_to_file(filename, &entry, keys)?;
+    save_history(settings, snapshot)?;
+
+    Ok(text)
+}
+
+// Returns the message of the removed task.
+fn remove_task(
+    filename: &str,
+    selection: &tasks::Selection,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<String, Box<dyn Error>> {
+    let mut entry = files::entry_from_file(filename, settings.max_size, keys)?;
+    let ix = select_task(&entry, selection)?;
+
+    let task = entry.remove_task(ix);
+    println!("removed: {}", task);
+
+    let snapshot = snapshot_history(settings, &[filename])?;
+    files::entry_to_file(filename, &entry, keys)?;
+    save_history(settings, snapshot)?;
+
+    Ok(task.text().to_string())
+}
+
+// Returns the message of the moved task. Only manual ordering allows
+// this, since the other orders would just move it back.
+fn move_task(
+    filename: &str,
+    selection: &tasks::Selection,
+    position: usize,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<String, Box<dyn Error>> {
+    let kept = match settings.order {
+        entry::TaskOrder::Sorted => "sorted",
+        entry::TaskOrder::Insertion => "kept in the order they were added",
+        entry::TaskOrder::Manual => "",
+    };
+    if settings.order != entry::TaskOrder::Manual {
+        return Err(Box::new(CommandError {
+            desc: format!(
+                "tasks are {}, so they can't be moved. To move tasks, use --task-order manual, \
+                 set COACH_TASK_ORDER=manual, or set task_order = \"manual\" in the config file",
+                kept
+            ),
+        }));
+    }
+
+    let mut entry = files::entry_from_file(filename, settings.max_size, keys)?;
+    let from = select_task(&entry, selection)?;
+    let to = item_index(entry.tasks.len(), position, "task")?;
+
+    entry.move_task(from, to);
+    for (ix, t) in entry.tasks.iter().enumerate() {
+        println!("{}: {}", ix + 1, t)
+    }
+
+    let text = entry.tasks[to].message().to_string();
+    let snapshot = snapshot_history(settings, &[filename])?;
+    files::entry_to_file(filename, &entry, keys)?;
+    save_history(settings, snapshot)?;
+
+    Ok(text)
+}
+
+// Returns the positions of the selected tasks in entry. If the selection
+// is ambiguous, the candidates are listed instead.
+fn select_tasks(
+    entry: &entry::Entry,
+    selection: &tasks::Selection,
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    match selection.resolve(&entry.tasks) {
+        Ok(indexes) => Ok(indexes),
+        Err(tasks::SelectError::Ambiguous(text, candidates)) => {
+            for candidate in candidates {
+                println!("{}", candidate);
+            }
+            Err(Box::new(CommandError {
+                desc: format!(
+                    "{:?} matches more than one task. Use more of its text, or its index",
+                    text
+                ),
+            }))
+        }
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+fn select_task(
+    entry: &entry::Entry,
+    selection: &tasks::Selection,
+) -> Result<usize, Box<dyn Error>> {
+    match select_tasks(entry, selection)?[..] {
+        [ix] => Ok(ix),
+        _ => Err(Box::new(tasks::SelectError::NotOne)),
+    }
+}
+
+fn task_selection(args: &clap::ArgMatches) -> tasks::Selection {
+    match args.value_of("match") {
+        Some(text) => tasks::Selection::Matching(text.to_string()),
+        None => tasks::parse_selection(args.value_of("TASK").unwrap()),
+    }
+}
+
+// Turns an index as shown by commands like coach task, starting at 1,
+// into an index into a list of len items of the kind named by what.
+fn item_index(len: usize, ix_plus_one: usize, what: &str) -> Result<usize, CommandError> {
+    if ix_plus_one == 0 {
+        return Err(CommandError {
+            desc: format!("{} indexes start at 1", what),
+        });
+    }
+
+    let ix = ix_plus_one - 1;
+    if ix >= len {
+        return Err(CommandError {
+            desc: format!("{} is to large, no {} found", ix_plus_one, what),
+        });
+    }
+
+    Ok(ix)
+}
+
+// Saves the current contents of filenames, so that once the calling
+// command has written them it can be reverted with coach undo. Call this
+// right before writing.
+fn snapshot_history(
+    settings: &Settings,
+    filenames: &[&str],
+) -> Result<history::Snapshot, io::Error> {
+    history::snapshot(&settings.history_dir, filenames)
+}
+
+// Records snapshot as a change made by the calling command. Call this
+// only after the command's writes have succeeded, so a failed command
+// doesn't leave a change behind that undo would revert.
+fn save_history(settings: &Settings, snapshot: history::Snapshot) -> Result<(), io::Error> {
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("{:?}", arg)
+            } else {
+                arg
+            }
+        })
+        .collect();
+    let command = format!("coach {}", args.join(" "));
+    history::save(&settings.history_dir, &command, snapshot)
+}
+
+// Returns the path of the file named name in journal_dir.
+fn journal_path(journal_dir: &str, name: &str) -> String {
+    let path = std::path::Path::new(journal_dir).join(name);
+    path.to_str().unwrap().to_string()
+}
+
+// Commits filenames to the git repository holding the journal.
+// Call this after a command has finished writing them.
+fn commit_changes(settings: &Settings, filenames: &[&str], message: &str) -> Result<(), io::Error> {
+    if !git::is_repository(&settings.journal_dir)? {
+        return Err(io::Error::other("autocommit is turned on, but the journal isn't in a git repository. Your change was saved, but not committed",
+        ));
+    }
+
+    git::commit(&settings.journal_dir, filenames, message)
+}
+
+// Prints records for the json and ndjson output formats. Does nothing
+// for text output, which each command prints for itself.
+fn print_records(format: output::Format, records: &[serde_json::Value]) -> Result<(), io::Error> {
+    if format == output::Format::Text {
+        return Ok(());
+    }
+    output::write_records(&mut io::stdout(), format, records)
+}
+
+// Dates given as arguments are relative to today in the configured time
+// zone, so they can't be checked until that's been worked out.
+fn parse_date_arg(expr: &str, today: time::Date) -> Result<time::Date, CommandError> {
+    dates::parse_date(expr, today).ok_or_else(|| CommandError {
+        desc: format!(
+            "{:?} isn't a date. Dates must look like 2021-10-31, yesterday, -3, friday, last friday or next friday",
+            expr
+        ),
+    })
+}
+
+// Reads every entry in the journal from the date given by args' --since
+// to the one given by --until, if they were given.
+fn read_entries_between(
+    args: &clap::ArgMatches,
+    today: time::Date,
+    settings: &Settings,
+    keys: &crypt::Keyring,
+) -> Result<Vec<journal::JournalEntry>, Box<dyn Error>> {
+    let since = match args.value_of("since") {
+        Some(expr) => Some(parse_date_arg(expr, today)?),
+        None => None,
+    };
+    let until = match args.value_of("until") {
+        Some(expr) => Some(parse_date_arg(expr, today)?),
+        None => None,
+    };
+    let (mut entries, problems) = journal::read_entries(
+        &settings.journal_dir,
+        &settings.index_file,
+        settings.max_size,
+        keys,
+    )?;
+    print_problems(&problems);
+    entries.retain(|e| {
+        !(since.is_some_and(|since| e.date < since) || until.is_some_and(|until| e.date > until))
+    });
+
+    Ok(entries)
+}
+
+fn stale_line(stale: &carried::Stale) -> String {
+    format!(
+        "{}: {} (carried {} {}, since {})",
+        stale.index,
+        stale.task,
+        stale.days,
+        if stale.days == 1 { "day" } else { "days" },
+        stale.first_seen.format(&DATE_FORMAT).unwrap()
+    )
+}
+
+fn print_problems(problems: &[journal::Problem]) {
+    for problem in problems {
+        eprintln!("warning: skipping {}: {}", problem.name, problem.error);
+    }
+}
+
+// Returns the names of every file touched by archiving or unarchiving bundles.
+fn bundle_filenames(settings: &Settings, bundles: &[archive::Bundle]) -> Vec<String> {
+    let mut filenames = vec![];
+    for bundle in bundles {
+        filenames.push(bundle.archive.clone());
+        for name in bundle.names.iter() {
+            let path = std::path::Path::new(&settings.journal_dir).join(name);
+            filenames.push(path.to_str().unwrap().to_string());
+        }
+    }
+    filenames
+}
+
+// Parses the setting named name, which must have a value, or explains
+// what's wrong with it and where it came from.
+fn setting<T, F>(config: &config::Config, name: &str, parse: F) -> Result<T, CommandError>
+where
+    F: FnOnce(&str) -> Option<T>,
+{
+    let setting = config.setting(name);
+    let value = setting.value.as_deref().unwrap();
+    parse(value).ok_or_else(|| CommandError {
+        desc: format!(
+            "{:?} from {} isn't a valid value for {}",
+            value, setting.source, name
+        ),
+    })
+}
+
+// Returns the current time, and whether its time zone was guessed.
+// Without an explicit time zone, the local time zone is used if it can be
+// found, and UTC if not. An explicit time zone must always be found.
+fn current_time(
+    timezone: Option<dates::Timezone>,
+) -> Result<(OffsetDateTime, bool), Box<dyn Error>> {
+    match timezone {
+        None => match OffsetDateTime::now_local() {
+            Ok(now) => Ok((now, false)),
+            Err(_) => Ok((OffsetDateTime::now_utc(), true)),
+        },
+        Some(dates::Timezone::Local) => Ok((OffsetDateTime::now_local()?, false)),
+        Some(dates::Timezone::Fixed(offset)) => {
+            Ok((OffsetDateTime::now_utc().to_offset(offset), false))
+        }
+        Some(dates::Timezone::Named(name)) => {
+            let now = OffsetDateTime::now_utc();
+            Ok((now.to_offset(zoneinfo::offset_at(&name, now)?), false))
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use serde_json::{json, Value};
+use std::io;
+use std::io::Write;
+use time::format_description::well_known::Rfc3339;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+use crate::carried::Stale;
+use crate::config::Setting;
+use crate::diff;
+use crate::entry::{Entry, Event, Note, Observation, Task};
+use crate::history::Change;
+use crate::journal::{JournalEntry, DATE_FORMAT};
+use crate::query::{Found, Item};
+use crate::search::Match;
+use crate::stats::Stats;
+use crate::timesheet::{Interval, Total};
+
+// Listing commands can print records as JSON for scripts to read. The
+// shape of each kind of record is documented in the README, and fields
+// should only ever be added to them, never changed or removed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    // A single JSON value: an array of records, or one record for
+    // commands that only ever print one.
+    Json,
+    // One JSON record per line.
+    Ndjson,
+}
+
+// Event times have no offset, since entries don't record one, so they're
+// written as local times rather than made to look like instants.
+const LOCAL_TIME_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+
+fn timestamp(when: &OffsetDateTime) -> Value {
+    json!(when.format(&Rfc3339).unwrap())
+}
+
+fn local_time(when: &PrimitiveDateTime) -> Value {
+    json!(when.format(&LOCAL_TIME_FORMAT).unwrap())
+}
+
+// index counts from 1, like the indexes printed by coach task.
+pub fn task_record(index: usize, task: &Task) -> Value {
+    json!({
+        "index": index,
+        "state": task.state(),
+        "message": task.text(),
+    })
+}
+
+// A task listed by coach task --stale, with the day it was first seen and
+// how many days it's been carried since.
+pub fn stale_record(stale: &Stale) -> Value {
+    let mut record = task_record(stale.index, stale.task);
+    record["first_seen"] = json!(stale.first_seen.format(&DATE_FORMAT).unwrap());
+    record["days"] = json!(stale.days);
+
+    record
+}
+
+pub fn observation_record(observation: &Observation) -> Value {
+    json!({
+        "name": observation.name.to_string(),
+        "value": observation.value.to_string(),
+    })
+}
+
+pub fn event_record(event: &Event) -> Value {
+    match event {
+        Event::Moment { when, text } => json!({
+            "kind": "moment",
+            "time": local_time(when),
+            "text": text.to_string(),
+        }),
+        Event::Interval { begin, end, text } => json!({
+            "kind": "interval",
+            "begin": local_time(begin),
+            "end": local_time(end),
+            "text": text.to_string(),
+        }),
+    }
+}
+
+pub fn note_record(index: usize, note: &Note) -> Value {
+    json!({
+        "index": index,
+        "text": note.to_string(),
+    })
+}
+
+pub fn entry_record(entry: &Entry) -> Value {
+    json!({
+        "label": entry.label.to_string(),
+        "observations": entry.observations.iter().map(observation_record).collect::<Vec<_>>(),
+        "tasks": entry
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(ix, t)| task_record(ix + 1, t))
+            .collect::<Vec<_>>(),
+        "events": entry.events.iter().map(event_record).collect::<Vec<_>>(),
+        "notes": entry
+            .notes
+            .iter()
+            .enumerate()
+            .map(|(ix, n)| note_record(ix + 1, n))
+            .collect::<Vec<_>>(),
+    })
+}
+
+// One value of an observation, and the entry it came from.
+pub fn observation_history_record(
+    journal_entry: &JournalEntry,
+    observation: &Observation,
+) -> Value {
+    json!({
+        "entry": journal_entry.name,
+        "name": observation.name.to_string(),
+        "value": observation.value.to_string(),
+    })
+}
+
+// A line found by coach grep, and the entry it came from.
+pub fn match_record(journal_entry: &JournalEntry, found: &Match) -> Value {
+    json!({
+        "entry": journal_entry.name,
+        "section": found.section.to_string(),
+        "index": found.index,
+        "line": found.line,
+    })
+}
+
+// Something coach query found. The fields of the task, event or
+// observation record are included, along with the entry it came from, its
+// type, and its index in that part of the entry.
+pub fn found_record(found: &Found) -> Value {
+    let mut record = match found.item {
+        Item::Task(task) => task_record(found.index, task),
+        Item::Event(event) => event_record(event),
+        Item::Observation(observation) => observation_record(observation),
+    };
+    record["entry"] = json!(found.journal_entry.name);
+    record["type"] = json!(found.item.kind().to_string());
+    record["index"] = json!(found.index);
+
+    record
+}
+
+pub fn interval_record(interval: &Interval) -> Value {
+    json!({
+        "entry": interval.journal_entry.name,
+        "begin": local_time(&interval.begin),
+        "end": local_time(&interval.end),
+        "minutes": interval.duration().whole_minutes(),
+        "text": interval.text,
+    })
+}
+
+// name is the day, the Monday the week starts on, or the tag.
+pub fn total_record(total: &Total) -> Value {
+    json!({
+        "name": total.name,
+        "minutes": total.duration.whole_minutes(),
+        "intervals": total.intervals,
+    })
+}
+
+// offset is the current time zone's, which the time is given in.
+pub fn change_record(change: &Change, offset: UtcOffset) -> Value {
+    json!({
+        "time": timestamp(&change.when.to_offset(offset)),
+        "command": change.command,
+        "files": change.filenames,
+    })
+}
+
+// value is null for settings that aren't set.
+pub fn setting_record(setting: &Setting) -> Value {
+    json!({
+        "name": setting.name,
+        "value": setting.value,
+        "source": setting.source.to_string(),
+    })
+}
+
+// Writes records to out in format, which must be Json or Ndjson.
+pub fn write_records<W: Write>(out: &mut W, format: Format, records: &[Value]) -> io::Result<()> {
+    match format {
+        Format::Json => writeln!(out, "{}", Value::Array(records.to_vec())),
+        _ => {
+            for record in records {
+                writeln!(out, "{}", record)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Like write_records, for commands that only ever print one record.
+pub fn write_record<W: Write>(out: &mut W, record: &Value) -> io::Result<()> {
+    writeln!(out, "{}", record)
+}
+
+// A change found by coach diff. from and to are null when there's nothing
+// there, like from for something added.
+pub fn diff_record(change: &diff::Change) -> Value {
+    json!({
+        "section": change.section.to_string(),
+        "change": change.kind.to_string(),
+        "name": change.name,
+        "from": change.from,
+        "to": change.to,
+    })
+}
+
+// The stats printed by coach stats. completion_rate runs from 0 to 1, and
+// it and days_to_done are null when there's nothing to work them out from.
+pub fn stats_record(stats: &Stats) -> Value {
+    let pairs = |pairs: &[(String, usize)], key: &str| -> Vec<Value> {
+        pairs
+            .iter()
+            .map(|(name, count)| json!({ "name": name, key: count }))
+            .collect()
+    };
+    json!({
+        "entries": stats.entries,
+        "months": pairs(&stats.months, "entries"),
+        "tasks": {
+            "created": stats.tasks,
+            "done": stats.done,
+            "cancelled": stats.cancelled,
+            "open": stats.open,
+            "completion_rate": stats.completion_rate(),
+            "days_to_done": stats.days_to_done,
+        },
+        "busiest": pairs(&stats.busiest, "events"),
+        "notes": stats.notes,
+        "note_words": stats.note_words,
+    })
+}
+
+// Writes rows as columns lined up under header, for people rather than
+// scripts to read. The last column isn't padded.
+pub fn write_table<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<String>]) -> io::Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let mut line = String::new();
+        for (ix, cell) in row.iter().enumerate() {
+            if ix + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:width$}  ", cell, width = widths[ix]));
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{as_no_newlines, as_observation_name};
+    use time::macros::datetime;
+
+    #[test]
+    fn test_records() {
+        let task = Task::Working(as_no_newlines(String::from("say \"hi\"")).unwrap());
+        assert_eq!(
+            r#"{"index":2,"message":"say \"hi\"","state":"working"}"#,
+            task_record(2, &task).to_string()
+        );
+
+        let event = Event::Moment {
+            when: datetime!(2021-10-31 09:30),
+            text: as_no_newlines(String::from("candy")).unwrap(),
+        };
+        assert_eq!(
+            r#"{"kind":"moment","text":"candy","time":"2021-10-31T09:30:00"}"#,
+            event_record(&event).to_string()
+        );
+
+        let observation = Observation {
+            name: as_observation_name(String::from("weather")).unwrap(),
+            value: as_no_newlines(String::from("windy")).unwrap(),
+        };
+        let mut out = vec![];
+        let records = vec![observation_record(&observation); 2];
+        write_records(&mut out, Format::Ndjson, &records).unwrap();
+        assert_eq!(
+            "{\"name\":\"weather\",\"value\":\"windy\"}\n{\"name\":\"weather\",\"value\":\"windy\"}\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use std::error::Error;
+use std::fmt;
+use time::Date;
+
+use crate::dates;
+use crate::entry::{Event, Observation, Task};
+use crate::journal::JournalEntry;
+
+// A query picks out tasks, events and observations from across the
+// journal. It's a list of terms separated by spaces, all of which an item
+// has to match:
+//
+//   type:task          only tasks. Also event, observation
+//   state:todo,done    tasks that are todo or done
+//   tag:release        items with #release in their text
+//   name:weather       observations named weather
+//   text:offsite       items with offsite in their text, ignoring case
+//   offsite            the same as text:offsite
+//   since:2024-01-01   items in entries from that date on
+//   until:yesterday    items in entries up to and including yesterday
+//
+// A comma separated list of values matches any of them, a term starting
+// with - matches everything the term wouldn't, and values with spaces in
+// them can be put in double quotes, like text:"lunch with Sam". Terms about
+// something an item doesn't have, like the state of an event, never match
+// it, even with a -.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Task,
+    Event,
+    Observation,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Task => "task",
+            Kind::Event => "event",
+            Kind::Observation => "observation",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    UnknownField(String),
+    BadValue(String, String),
+    // Since and until are about entries, not items, so -since:DATE is
+    // better written as until:DATE.
+    CantNegate(String),
+    UnclosedQuote,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::UnknownField(field) => write!(
+                f,
+                "there's no field named {}. Fields are type, state, tag, name, text, since and until",
+                field
+            ),
+            QueryError::BadValue(field, value) => {
+                write!(f, "{:?} isn't a valid value for {}", value, field)
+            }
+            QueryError::CantNegate(field) => write!(f, "{} can't be negated with -", field),
+            QueryError::UnclosedQuote => write!(f, "the query has a \" without a matching \""),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+#[derive(Debug, PartialEq)]
+enum Condition {
+    Kind(Vec<Kind>),
+    State(Vec<String>),
+    Tag(Vec<String>),
+    Name(Vec<String>),
+    Text(Vec<String>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+    since: Option<Date>,
+    until: Option<Date>,
+}
+
+// One of the things a query can find in an entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Item<'a> {
+    Task(&'a Task),
+    Event(&'a Event),
+    Observation(&'a Observation),
+}
+
+impl Item<'_> {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Item::Task(_) => Kind::Task,
+            Item::Event(_) => Kind::Event,
+            Item::Observation(_) => Kind::Observation,
+        }
+    }
+
+    // The text that tags and text terms look in.
+    fn text(&self) -> String {
+        match self {
+            Item::Task(task) => task.text().to_string(),
+            Item::Event(Event::Moment { text, .. }) | Item::Event(Event::Interval { text, .. }) => {
+                text.to_string()
+            }
+            Item::Observation(observation) => observation.to_string(),
+        }
+    }
+}
+
+// The item as it's written in the entry.
+impl fmt::Display for Item<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Task(task) => write!(f, "{}", task),
+            Item::Event(event) => write!(f, "{}", event),
+            Item::Observation(observation) => write!(f, "{}", observation),
+        }
+    }
+}
+
+// An item a query found, the entry it's in, and its index in its section of
+// the entry, counting from 1.
+pub struct Found<'a> {
+    pub journal_entry: &'a JournalEntry,
+    pub index: usize,
+    pub item: Item<'a>,
+}
+
+// Splits query into terms at spaces, except for spaces inside quotes.
+fn split_terms(query: &str) -> Result<Vec<String>, QueryError> {
+    let mut terms = vec![];
+    let mut term = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                term.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+    if quoted {
+        return Err(QueryError::UnclosedQuote);
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+
+    Ok(terms)
+}
+
+fn parse_kind(value: &str) -> Option<Kind> {
+    match value {
+        "task" | "tasks" => Some(Kind::Task),
+        "event" | "events" => Some(Kind::Event),
+        "observation" | "observations" => Some(Kind::Observation),
+        _ => None,
+    }
+}
+
+// Parses query. Dates like yesterday are taken relative to today.
+pub fn parse(query: &str, today: Date) -> Result<Query, QueryError> {
+    let mut parsed = Query {
+        terms: vec![],
+        since: None,
+        until: None,
+    };
+
+    for term in split_terms(query)? {
+        let (negated, term) = match term.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, term.as_str()),
+        };
+        // A colon inside quotes doesn't start a field.
+        let unquoted_prefix = term.split('"').next().unwrap();
+        let (field, value) = match unquoted_prefix.split_once(':') {
+            Some((name, _)) => (name.to_lowercase(), &term[name.len() + 1..]),
+            None => (String::from("text"), term),
+        };
+        let value = value.replace('"', "");
+        let bad_value = || QueryError::BadValue(field.clone(), value.clone());
+        let values = || -> Result<Vec<String>, QueryError> {
+            let values: Vec<String> = value
+                .split(',')
+                .map(|v| v.trim().to_lowercase())
+                .filter(|v| !v.is_empty())
+                .collect();
+            if values.is_empty() {
+                return Err(bad_value());
+            }
+            Ok(values)
+        };
+
+        let condition = match field.as_str() {
+            "since" | "until" => {
+                if negated {
+                    return Err(QueryError::CantNegate(field));
+                }
+                let date = dates::parse_date(&value, today).ok_or_else(bad_value)?;
+                match field.as_str() {
+                    "since" => parsed.since = Some(date),
+                    _ => parsed.until = Some(date),
+                }
+                continue;
+            }
+            "type" => Condition::Kind(
+                values()?
+                    .iter()
+                    .map(|v| parse_kind(v))
+                    .collect::<Option<_>>()
+                    .ok_or_else(bad_value)?,
+            ),
+            "state" => {
+                let states = values()?;
+                let known = ["todo", "working", "done", "cancelled"];
+                if states.iter().any(|s| !known.contains(&s.as_str())) {
+                    return Err(bad_value());
+                }
+                Condition::State(states)
+            }
+            "tag" => Condition::Tag(
+                values()?
+                    .into_iter()
+                    .map(|v| v.trim_start_matches('#').to_string())
+                    .collect(),
+            ),
+            "name" => Condition::Name(values()?),
+            // Text can have commas in it, so it's never a list.
+            "text" => Condition::Text(vec![value.to_lowercase()]),
+            _ => return Err(QueryError::UnknownField(field)),
+        };
+        parsed.terms.push(Term { negated, condition });
+    }
+
+    Ok(parsed)
+}
+
+// The tags in text, which are words starting with #, like #release. Tags
+// can have letters, numbers, - and _ in them.
+pub fn tags(text: &str) -> Vec<&str> {
+    let is_tag_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    text.match_indices('#')
+        .map(|(ix, _)| {
+            let rest = &text[ix + 1..];
+            let len = rest.find(|c| !is_tag_char(c)).unwrap_or(rest.len());
+            &rest[..len]
+        })
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+impl Condition {
+    // None if the condition is about something item doesn't have.
+    fn matches(&self, item: &Item) -> Option<bool> {
+        let text = item.text().to_lowercase();
+        match (self, item) {
+            (Condition::Kind(kinds), _) => Some(kinds.contains(&item.kind())),
+            (Condition::State(states), Item::Task(task)) => {
+                Some(states.iter().any(|s| s == task.state()))
+            }
+            (Condition::State(_), _) => None,
+            (Condition::Tag(wanted), _) => Some(
+                tags(&text)
+                    .iter()
+                    .any(|tag| wanted.iter().any(|w| w == tag)),
+            ),
+            (Condition::Name(names), Item::Observation(observation)) => {
+                let name = observation.name.to_string().to_lowercase();
+                Some(names.contains(&name))
+            }
+            (Condition::Name(_), _) => None,
+            (Condition::Text(words), _) => Some(words.iter().any(|w| text.contains(w.as_str()))),
+        }
+    }
+}
+
+impl Query {
+    pub fn matches(&self, item: &Item) -> bool {
+        self.terms
+            .iter()
+            .all(|term| match term.condition.matches(item) {
+                Some(matched) => matched != term.negated,
+                None => false,
+            })
+    }
+
+    // Whether items in an entry from date could match.
+    pub fn includes_date(&self, date: Date) -> bool {
+        self.since.map_or(true, |since| date >= since)
+            && self.until.map_or(true, |until| date <= until)
+    }
+
+    // Finds everything in entries that matches, in entry order, with each
+    // entry's tasks first, then its events, then its observations.
+    pub fn run<'a>(&self, entries: &'a [JournalEntry]) -> Vec<Found<'a>> {
+        let mut found = vec![];
+        for journal_entry in entries.iter().filter(|e| self.includes_date(e.date)) {
+            let entry = &journal_entry.entry;
+            let sections: [Vec<Item>; 3] = [
+                entry.tasks.iter().map(Item::Task).collect(),
+                entry.events.iter().map(Item::Event).collect(),
+                entry.observations.iter().map(Item::Observation).collect(),
+            ];
+            for items in sections.iter() {
+                for (ix, item) in items.iter().enumerate() {
+                    if self.matches(item) {
+                        found.push(Found {
+                            journal_entry,
+                            index: ix + 1,
+                            item: *item,
+                        });
+                    }
+                }
+            }
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use time::macros::date;
+
+    const TODAY: Date = date!(2024 - 10 - 18);
+
+    fn found(query: &str, entries: &[JournalEntry]) -> Vec<String> {
+        parse(query, TODAY)
+            .unwrap()
+            .run(entries)
+            .iter()
+            .map(|f| format!("{} {}", f.journal_entry.name, f.item))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Query {
+                terms: vec![
+                    Term {
+                        negated: false,
+                        condition: Condition::State(vec![
+                            String::from("todo"),
+                            String::from("done")
+                        ]),
+                    },
+                    Term {
+                        negated: true,
+                        condition: Condition::Text(vec![String::from("lunch: with sam")]),
+                    },
+                ],
+                since: Some(date!(2024 - 10 - 17)),
+                until: None,
+            },
+            parse(
+                "state:todo,DONE  -text:\"Lunch: with Sam\" since:yesterday",
+                TODAY
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Err(QueryError::UnknownField(String::from("colour"))),
+            parse("colour:red", TODAY)
+        );
+        assert_eq!(
+            Err(QueryError::BadValue(
+                String::from("state"),
+                String::from("finished")
+            )),
+            parse("state:finished", TODAY)
+        );
+        assert_eq!(Err(QueryError::UnclosedQuote), parse("\"lunch", TODAY));
+        assert_eq!(
+            Err(QueryError::CantNegate(String::from("since"))),
+            parse("-since:2024-01-01", TODAY)
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let journal_entry = |name: &str, text: &str| JournalEntry {
+            name: name.to_string(),
+            date: crate::journal::entry_date(name).unwrap(),
+            entry: entry::parse(text).unwrap(),
+        };
+        let entries = vec![
+            journal_entry(
+                "2024-01-02",
+                "#coach\n2024-01-02\nweather: rainy\n\nCANCELLED ship #release-notes\nDONE ship #release\n",
+            ),
+            journal_entry(
+                "2024-02-01",
+                "#coach\n2024-02-01\nweather: sunny\n\nCANCELLED plan #release party\nTODO lunch\n",
+            ),
+        ];
+
+        assert_eq!(
+            vec![
+                "2024-01-02 DONE ship #release",
+                "2024-02-01 CANCELLED plan #release party",
+            ],
+            found("tag:release", &entries)
+        );
+        assert_eq!(
+            vec!["2024-02-01 CANCELLED plan #release party"],
+            found("state:cancelled since:2024-02-01", &entries)
+        );
+        assert_eq!(
+            vec!["2024-01-02 DONE ship #release", "2024-02-01 TODO lunch"],
+            found("-state:cancelled", &entries)
+        );
+        assert_eq!(
+            vec!["2024-02-01 weather: sunny"],
+            found("name:Weather sun", &entries)
+        );
+        assert_eq!(4, found("type:task until:2024-12-31", &entries).len());
+    }
+}
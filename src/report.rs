@@ -0,0 +1,283 @@
+use std::io;
+use std::io::Write;
+use time::{Date, Duration};
+
+use crate::entry::{Entry, Task};
+use crate::journal::DATE_FORMAT;
+
+// Reports sum up the entries for a week or a month, for pasting into
+// status updates.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    // Monday to Sunday.
+    Week,
+    Month,
+}
+
+// The first and last days of the period that date is in.
+pub fn period_dates(period: Period, date: Date) -> (Date, Date) {
+    match period {
+        Period::Week => {
+            let first = date - Duration::days(date.weekday().number_days_from_monday() as i64);
+            (first, first + Duration::days(6))
+        }
+        Period::Month => {
+            let (year, month) = (date.year(), date.month());
+            let days = time::util::days_in_year_month(year, month);
+            (
+                Date::from_calendar_date(year, month, 1).unwrap(),
+                Date::from_calendar_date(year, month, days).unwrap(),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub entries: usize,
+    // Tasks are usually migrated from day to day, so each task is only
+    // listed once, in the state it was in in the latest entry it's in.
+    pub done: Vec<String>,
+    pub cancelled: Vec<String>,
+    pub open: Vec<String>,
+    // Events, and notes, along with the name of the entry they're in.
+    pub events: Vec<(String, String)>,
+    // Each observation's name, and the values it had, and in which entry.
+    pub observations: Vec<(String, Vec<(String, String)>)>,
+    pub notes: Vec<(String, String)>,
+}
+
+// Sums up entries, which are named and in date order.
+pub fn build(entries: &[(String, Entry)]) -> Report {
+    let mut report = Report {
+        entries: entries.len(),
+        ..Default::default()
+    };
+
+    let mut tasks: Vec<&Task> = vec![];
+    for (name, entry) in entries {
+        for task in &entry.tasks {
+            match tasks.iter_mut().find(|t| t.text() == task.text()) {
+                Some(latest) => *latest = task,
+                None => tasks.push(task),
+            }
+        }
+        for event in &entry.events {
+            report.events.push((name.clone(), event.to_string()));
+        }
+        for observation in &entry.observations {
+            let value = (name.clone(), observation.value.to_string());
+            let observation_name = observation.name.to_string();
+            match report
+                .observations
+                .iter_mut()
+                .find(|(n, _)| *n == observation_name)
+            {
+                Some((_, values)) => values.push(value),
+                None => report.observations.push((observation_name, vec![value])),
+            }
+        }
+        for note in &entry.notes {
+            report
+                .notes
+                .push((name.clone(), note.to_string().trim_end().to_string()));
+        }
+    }
+
+    for task in tasks {
+        match task {
+            Task::Done(_) => report.done.push(task.text().to_string()),
+            Task::Cancelled(_) => report.cancelled.push(task.text().to_string()),
+            _ => report.open.push(task.to_string()),
+        }
+    }
+
+    report
+}
+
+fn observation_values(values: &[(String, String)]) -> String {
+    values
+        .iter()
+        .map(|(name, value)| format!("{} ({})", value, name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Writes report for the days from first to last. Sections with nothing in
+// them are left out.
+pub fn write<W: Write>(
+    out: &mut W,
+    report: &Report,
+    first: Date,
+    last: Date,
+    markdown: bool,
+) -> io::Result<()> {
+    let first = first.format(&DATE_FORMAT).unwrap();
+    let last = last.format(&DATE_FORMAT).unwrap();
+    let title = format!("{} to {}", first, last);
+    let entries = match report.entries {
+        1 => String::from("1 entry"),
+        n => format!("{} entries", n),
+    };
+
+    let lists = [
+        ("Done", &report.done),
+        ("Cancelled", &report.cancelled),
+        ("Still open", &report.open),
+    ];
+    let events: Vec<String> = report
+        .events
+        .iter()
+        .map(|(name, event)| format!("{} {}", name, event))
+        .collect();
+    let observations: Vec<String> = report
+        .observations
+        .iter()
+        .map(|(name, values)| format!("{}: {}", name, observation_values(values)))
+        .collect();
+
+    if markdown {
+        writeln!(out, "## {}", title)?;
+        writeln!(out)?;
+        writeln!(out, "_{}_", entries)?;
+        for (heading, items) in lists
+            .iter()
+            .copied()
+            .chain([("Events", &events), ("Observations", &observations)])
+        {
+            if items.is_empty() {
+                continue;
+            }
+            writeln!(out)?;
+            writeln!(out, "### {}", heading)?;
+            writeln!(out)?;
+            for item in items.iter() {
+                writeln!(out, "- {}", item)?;
+            }
+        }
+        if !report.notes.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "### Notes")?;
+            for (name, note) in &report.notes {
+                writeln!(out)?;
+                writeln!(out, "**{}**", name)?;
+                writeln!(out)?;
+                writeln!(out, "{}", note)?;
+            }
+        }
+    } else {
+        writeln!(out, "{} ({})", title, entries)?;
+        for (heading, items) in lists
+            .iter()
+            .copied()
+            .chain([("Events", &events), ("Observations", &observations)])
+        {
+            if items.is_empty() {
+                continue;
+            }
+            writeln!(out)?;
+            writeln!(out, "{} ({})", heading, items.len())?;
+            for item in items.iter() {
+                writeln!(out, "  {}", item)?;
+            }
+        }
+        if !report.notes.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Notes ({})", report.notes.len())?;
+            for (name, note) in &report.notes {
+                writeln!(out, "  {}", name)?;
+                for line in note.lines() {
+                    writeln!(out, "    {}", line)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use time::macros::date;
+
+    #[test]
+    fn test_period_dates() {
+        assert_eq!(
+            (date!(2021 - 10 - 25), date!(2021 - 10 - 31)),
+            period_dates(Period::Week, date!(2021 - 10 - 31))
+        );
+        assert_eq!(
+            (date!(2021 - 11 - 01), date!(2021 - 11 - 07)),
+            period_dates(Period::Week, date!(2021 - 11 - 01))
+        );
+        assert_eq!(
+            (date!(2024 - 02 - 01), date!(2024 - 02 - 29)),
+            period_dates(Period::Month, date!(2024 - 02 - 14))
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let entries = vec![
+            (
+                String::from("2021-10-30"),
+                entry::parse("#coach\n2021-10-30\nmood: ok\n\nTODO buy candy\nTODO costume\nDONE carve pumpkin\n\nStill no candy.\n").unwrap(),
+            ),
+            (
+                String::from("2021-10-31"),
+                entry::parse("#coach\n2021-10-31\nmood: good\n\nWORKING costume\nDONE buy candy\n").unwrap(),
+            ),
+        ];
+        let report = build(&entries);
+        assert_eq!(
+            Report {
+                entries: 2,
+                done: vec![String::from("buy candy"), String::from("carve pumpkin")],
+                cancelled: vec![],
+                open: vec![String::from("WORKING costume")],
+                events: vec![],
+                observations: vec![(
+                    String::from("mood"),
+                    vec![
+                        (String::from("2021-10-30"), String::from("ok")),
+                        (String::from("2021-10-31"), String::from("good")),
+                    ]
+                )],
+                notes: vec![(String::from("2021-10-30"), String::from("Still no candy."))],
+            },
+            report
+        );
+
+        let mut out = vec![];
+        write(
+            &mut out,
+            &report,
+            date!(2021 - 10 - 25),
+            date!(2021 - 10 - 31),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            "2021-10-25 to 2021-10-31 (2 entries)
+
+Done (2)
+  buy candy
+  carve pumpkin
+
+Still open (1)
+  WORKING costume
+
+Observations (1)
+  mood: ok (2021-10-30), good (2021-10-31)
+
+Notes (1)
+  2021-10-30
+    Still no candy.
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use regex::Regex;
+use std::fmt;
+
+use crate::entry::Entry;
+
+// The parts of an entry that can be searched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Section {
+    Observations,
+    Tasks,
+    Events,
+    Notes,
+}
+
+pub const SECTIONS: [Section; 4] = [
+    Section::Observations,
+    Section::Tasks,
+    Section::Events,
+    Section::Notes,
+];
+
+impl Section {
+    // The name used to pick sections on the command line, like tasks.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Observations => "observations",
+            Section::Tasks => "tasks",
+            Section::Events => "events",
+            Section::Notes => "notes",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Section> {
+        SECTIONS.iter().copied().find(|s| s.name() == name)
+    }
+}
+
+// Sections are labelled in the singular, since each match is one item.
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Section::Observations => "observation",
+            Section::Tasks => "task",
+            Section::Events => "event",
+            Section::Notes => "note",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// A line that matched, and where it was in the entry. index counts from 1
+// within the section, so it's the same index coach task or coach note list
+// would show.
+#[derive(Debug, PartialEq)]
+pub struct Match {
+    pub section: Section,
+    pub index: usize,
+    pub line: String,
+}
+
+// Finds the lines in entry's sections that match regex. Each item is
+// matched as it's written in the entry, so a task's state can be searched
+// for too. Notes are matched a line at a time.
+pub fn search(entry: &Entry, regex: &Regex, sections: &[Section]) -> Vec<Match> {
+    let mut found = vec![];
+    for section in SECTIONS.iter().filter(|s| sections.contains(s)) {
+        let items: Vec<String> = match section {
+            Section::Observations => entry.observations.iter().map(|o| o.to_string()).collect(),
+            Section::Tasks => entry.tasks.iter().map(|t| t.to_string()).collect(),
+            Section::Events => entry.events.iter().map(|e| e.to_string()).collect(),
+            Section::Notes => entry.notes.iter().map(|n| n.to_string()).collect(),
+        };
+        for (ix, item) in items.iter().enumerate() {
+            for line in item.lines().filter(|line| regex.is_match(line)) {
+                found.push(Match {
+                    section: *section,
+                    index: ix + 1,
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+
+    #[test]
+    fn test_search() {
+        let entry = entry::parse(
+            "#coach\n2021-10-31\nweather: windy\n\nTODO write README\nDONE buy candy\n\nThe wind took the\ncandy wrappers.\n",
+        )
+        .unwrap();
+        let regex = Regex::new("(?i)wind|candy").unwrap();
+        assert_eq!(
+            vec![
+                Match {
+                    section: Section::Observations,
+                    index: 1,
+                    line: String::from("weather: windy"),
+                },
+                Match {
+                    section: Section::Tasks,
+                    index: 2,
+                    line: String::from("DONE buy candy"),
+                },
+                Match {
+                    section: Section::Notes,
+                    index: 1,
+                    line: String::from("The wind took the"),
+                },
+                Match {
+                    section: Section::Notes,
+                    index: 1,
+                    line: String::from("candy wrappers."),
+                },
+            ],
+            search(&entry, &regex, &SECTIONS)
+        );
+
+        let regex = Regex::new("^TODO").unwrap();
+        assert_eq!(1, search(&entry, &regex, &[Section::Tasks]).len());
+        assert!(search(&entry, &regex, &[Section::Notes]).is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use std::io;
+use std::io::Write;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+
+use crate::entry::{Entry, Event, Task};
+
+// A standup is what got done in the previous entry, and what's on for
+// today, for pasting into a team chat.
+
+const CLOCK_FORMAT: &[FormatItem<'static>] = format_description!("[hour repr:24]:[minute]");
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Standup {
+    // The name of the previous entry, if there is one.
+    pub previous: Option<String>,
+    pub done: Vec<String>,
+    pub events: Vec<String>,
+    pub working: Vec<String>,
+    pub todo: Vec<String>,
+    // Tasks cancelled in either entry. A task cancelled in both is only
+    // listed once.
+    pub cancelled: Vec<String>,
+}
+
+// Events are shown with just their times, since the day is in the heading.
+fn event_line(event: &Event) -> String {
+    match event {
+        Event::Moment { when, text } => format!("{} {}", when.format(&CLOCK_FORMAT).unwrap(), text),
+        Event::Interval { begin, end, text } => format!(
+            "{}-{} {}",
+            begin.format(&CLOCK_FORMAT).unwrap(),
+            end.format(&CLOCK_FORMAT).unwrap(),
+            text
+        ),
+    }
+}
+
+// previous is the name of the entry before today's, and the entry itself.
+pub fn build(previous: Option<(&str, &Entry)>, today: &Entry) -> Standup {
+    let mut standup = Standup::default();
+    let mut cancel = |task: &Task| {
+        let text = task.text().to_string();
+        if !standup.cancelled.contains(&text) {
+            standup.cancelled.push(text);
+        }
+    };
+
+    if let Some((_, entry)) = previous {
+        for task in &entry.tasks {
+            if let Task::Cancelled(_) = task {
+                cancel(task);
+            }
+        }
+    }
+    for task in &today.tasks {
+        if let Task::Cancelled(_) = task {
+            cancel(task);
+        }
+    }
+
+    if let Some((name, entry)) = previous {
+        standup.previous = Some(name.to_string());
+        for task in &entry.tasks {
+            if let Task::Done(_) = task {
+                standup.done.push(task.text().to_string());
+            }
+        }
+        standup.events = entry.events.iter().map(event_line).collect();
+    }
+    for task in &today.tasks {
+        match task {
+            Task::Working(_) => standup.working.push(task.text().to_string()),
+            Task::Todo(_) => standup.todo.push(task.text().to_string()),
+            _ => {}
+        }
+    }
+
+    standup
+}
+
+pub fn write<W: Write>(out: &mut W, standup: &Standup, markdown: bool) -> io::Result<()> {
+    let heading = |out: &mut W, title: &str, detail: Option<&str>| -> io::Result<()> {
+        let title = if markdown {
+            format!("**{}**", title)
+        } else {
+            title.to_string()
+        };
+        match detail {
+            Some(detail) => writeln!(out, "{} ({})", title, detail),
+            None => writeln!(out, "{}", title),
+        }
+    };
+    let item = |out: &mut W, text: &str| -> io::Result<()> {
+        if markdown {
+            writeln!(out, "- {}", text)
+        } else {
+            writeln!(out, "  {}", text)
+        }
+    };
+
+    heading(out, "Yesterday", standup.previous.as_deref())?;
+    let mut yesterday = vec![];
+    yesterday.extend(standup.done.iter().map(|t| format!("done: {}", t)));
+    yesterday.extend(standup.events.iter().cloned());
+    if standup.previous.is_none() {
+        item(out, "no earlier entry")?;
+    } else if yesterday.is_empty() {
+        item(out, "nothing recorded")?;
+    }
+    for line in yesterday {
+        item(out, &line)?;
+    }
+
+    writeln!(out)?;
+    heading(out, "Today", None)?;
+    let mut today = vec![];
+    today.extend(standup.working.iter().map(|t| format!("working on: {}", t)));
+    today.extend(standup.todo.iter().map(|t| format!("to do: {}", t)));
+    if today.is_empty() {
+        item(out, "nothing planned")?;
+    }
+    for line in today {
+        item(out, &line)?;
+    }
+
+    if !standup.cancelled.is_empty() {
+        writeln!(out)?;
+        heading(out, "Cancelled", None)?;
+        for task in &standup.cancelled {
+            item(out, task)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+
+    #[test]
+    fn test_standup() {
+        let friday = entry::parse(
+            "#coach\n2021-10-29\n\nDONE buy candy\nCANCELLED costume\nTODO carve pumpkin\n\n* <2021-10-29 Fri 10:03> bought candy\n",
+        )
+        .unwrap();
+        let monday = entry::parse(
+            "#coach\n2021-11-01\n\nWORKING eat candy\nTODO carve pumpkin\nCANCELLED costume\n",
+        )
+        .unwrap();
+        let standup = build(Some(("2021-10-29", &friday)), &monday);
+
+        let mut out = vec![];
+        write(&mut out, &standup, true).unwrap();
+        assert_eq!(
+            "**Yesterday** (2021-10-29)
+- done: buy candy
+- 10:03 bought candy
+
+**Today**
+- working on: eat candy
+- to do: carve pumpkin
+
+**Cancelled**
+- costume
+",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = vec![];
+        write(
+            &mut out,
+            &build(None, &entry::parse("#coach\n2021-11-01\n").unwrap()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            "Yesterday
+  no earlier entry
+
+Today
+  nothing planned
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::io::Write;
+use time::Date;
+
+use crate::carried::Carried;
+use crate::entry::Task;
+use crate::journal::JournalEntry;
+
+// Statistics across the journal, to give a feel for how much is getting
+// done.
+
+// How many of the days with the most events to list.
+pub const BUSIEST_DAYS: usize = 5;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub entries: usize,
+    // The number of entries in each month, named like 2021-10, in order.
+    pub months: Vec<(String, usize)>,
+    // Like coach report, tasks are counted once each, in the state they
+    // were in in the latest entry they're in.
+    pub tasks: usize,
+    pub done: usize,
+    pub cancelled: usize,
+    pub open: usize,
+    // The average number of days from a task first being seen to it being
+    // done, if any were done.
+    pub days_to_done: Option<f64>,
+    // The entries with the most events, and how many they have, busiest
+    // first.
+    pub busiest: Vec<(String, usize)>,
+    pub notes: usize,
+    pub note_words: usize,
+}
+
+impl Stats {
+    // The share of tasks that were done, from 0 to 1.
+    pub fn completion_rate(&self) -> Option<f64> {
+        if self.tasks == 0 {
+            return None;
+        }
+        Some(self.done as f64 / self.tasks as f64)
+    }
+}
+
+// Gathers stats for entries, which are in date order. Tasks that were
+// migrated count from the day they were first seen, as remembered in
+// carried.
+pub fn build(entries: &[JournalEntry], carried: &Carried) -> Stats {
+    let mut stats = Stats {
+        entries: entries.len(),
+        ..Default::default()
+    };
+
+    // Each task, with the date of the first entry it's in, and its state
+    // and date in the latest one.
+    let mut tasks: Vec<(&Task, Date, Date)> = vec![];
+    for journal_entry in entries {
+        let month = journal_entry.name.get(..7).unwrap_or(&journal_entry.name);
+        match stats.months.last_mut() {
+            Some((name, count)) if name == month => *count += 1,
+            _ => stats.months.push((month.to_string(), 1)),
+        }
+
+        let date = journal_entry.date;
+        for task in &journal_entry.entry.tasks {
+            match tasks.iter_mut().find(|(t, _, _)| t.text() == task.text()) {
+                Some((latest, _, latest_date)) => {
+                    *latest = task;
+                    *latest_date = date;
+                }
+                None => tasks.push((task, date, date)),
+            }
+        }
+
+        let events = journal_entry.entry.events.len();
+        if events > 0 {
+            stats.busiest.push((journal_entry.name.clone(), events));
+        }
+
+        for note in &journal_entry.entry.notes {
+            stats.notes += 1;
+            stats.note_words += note.to_string().split_whitespace().count();
+        }
+    }
+
+    let mut days_to_done = vec![];
+    for (task, first, latest) in &tasks {
+        match task {
+            Task::Done(_) => {
+                stats.done += 1;
+                let first = carried
+                    .first_seen(task.text(), *latest)
+                    .map_or(*first, |seen| seen.min(*first));
+                days_to_done.push((*latest - first).whole_days());
+            }
+            Task::Cancelled(_) => stats.cancelled += 1,
+            _ => stats.open += 1,
+        }
+    }
+    stats.tasks = tasks.len();
+    if !days_to_done.is_empty() {
+        let total: i64 = days_to_done.iter().sum();
+        stats.days_to_done = Some(total as f64 / days_to_done.len() as f64);
+    }
+
+    // The sort is stable, so days with the same number of events stay in
+    // date order.
+    stats.busiest.sort_by(|(_, a), (_, b)| b.cmp(a));
+    stats.busiest.truncate(BUSIEST_DAYS);
+
+    stats
+}
+
+fn plural(n: usize, one: &str, many: &str) -> String {
+    match n {
+        1 => format!("1 {}", one),
+        n => format!("{} {}", n, many),
+    }
+}
+
+// Writes stats for the entries from first to last, the names of the
+// earliest and latest entries.
+pub fn write<W: Write>(out: &mut W, stats: &Stats, first: &str, last: &str) -> io::Result<()> {
+    writeln!(
+        out,
+        "{} to {} ({})",
+        first,
+        last,
+        plural(stats.entries, "entry", "entries")
+    )?;
+
+    writeln!(out)?;
+    writeln!(out, "Entries per month")?;
+    for (month, count) in &stats.months {
+        writeln!(out, "  {}  {}", month, count)?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Tasks")?;
+    let width = stats.tasks.to_string().len();
+    writeln!(out, "  created    {:>width$}", stats.tasks, width = width)?;
+    match stats.completion_rate() {
+        Some(rate) => writeln!(
+            out,
+            "  done       {:>width$} ({:.0}%)",
+            stats.done,
+            rate * 100.0,
+            width = width
+        )?,
+        None => writeln!(out, "  done       {:>width$}", stats.done, width = width)?,
+    }
+    writeln!(
+        out,
+        "  cancelled  {:>width$}",
+        stats.cancelled,
+        width = width
+    )?;
+    writeln!(out, "  open       {:>width$}", stats.open, width = width)?;
+    if let Some(days) = stats.days_to_done {
+        writeln!(out, "  average time from TODO to DONE: {:.1} days", days)?;
+    }
+
+    if !stats.busiest.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "Busiest days")?;
+        for (name, events) in &stats.busiest {
+            writeln!(out, "  {}  {}", name, plural(*events, "event", "events"))?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Notes")?;
+    write!(
+        out,
+        "  {}, {}",
+        plural(stats.notes, "note", "notes"),
+        plural(stats.note_words, "word", "words")
+    )?;
+    if stats.entries > 0 {
+        write!(
+            out,
+            ", {:.0} per entry",
+            stats.note_words as f64 / stats.entries as f64
+        )?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use crate::journal::entry_date;
+    use time::macros::date;
+
+    fn journal_entry(name: &str, body: &str) -> JournalEntry {
+        JournalEntry {
+            name: name.to_string(),
+            date: entry_date(name).unwrap(),
+            entry: entry::parse(&format!("#coach\n{}\n\n{}", name, body)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("carried");
+        let mut carried = Carried::open(path.to_str().unwrap()).unwrap();
+        carried.seen("carve pumpkin", date!(2021 - 10 - 25));
+
+        let entries = vec![
+            journal_entry(
+                "2021-10-29",
+                "DONE buy candy\nCANCELLED costume\n\n* <2021-10-29 Fri 10:03> bought candy\n\nAll the good candy is gone.\n\n",
+            ),
+            journal_entry(
+                "2021-10-31",
+                "TODO carve pumpkin\nTODO rake\n\n* <2021-10-31 Sun 09:00> up\n* <2021-10-31 Sun 18:00> trick or treat\n",
+            ),
+            journal_entry(
+                "2021-11-01",
+                "DONE carve pumpkin\nTODO rake\n\nToo late.\n\n",
+            ),
+        ];
+        let stats = build(&entries, &carried);
+        assert_eq!(
+            Stats {
+                entries: 3,
+                months: vec![(String::from("2021-10"), 2), (String::from("2021-11"), 1)],
+                tasks: 4,
+                done: 2,
+                cancelled: 1,
+                open: 1,
+                days_to_done: Some(3.5),
+                busiest: vec![
+                    (String::from("2021-10-31"), 2),
+                    (String::from("2021-10-29"), 1)
+                ],
+                notes: 2,
+                note_words: 8,
+            },
+            stats
+        );
+        assert_eq!(Some(0.5), stats.completion_rate());
+
+        let mut out = vec![];
+        write(&mut out, &stats, "2021-10-29", "2021-11-01").unwrap();
+        assert_eq!(
+            "2021-10-29 to 2021-11-01 (3 entries)
+
+Entries per month
+  2021-10  2
+  2021-11  1
+
+Tasks
+  created    4
+  done       2 (50%)
+  cancelled  1
+  open       1
+  average time from TODO to DONE: 3.5 days
+
+Busiest days
+  2021-10-31  2 events
+  2021-10-29  1 event
+
+Notes
+  2 notes, 8 words, 3 per entry
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::entry::Task;
+
+// The tasks a command should apply to, either by their indexes as shown
+// by coach task, starting at 1, or by some text in their messages.
+#[derive(Debug, PartialEq)]
+pub enum Selection {
+    // Ranges of indexes, from first to last. A single index is a range
+    // that starts and ends with it.
+    Indexes(Vec<(usize, usize)>),
+    // Every task containing the text.
+    Matching(String),
+    // The one task the text picks out. See find_task.
+    Text(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SelectError {
+    NoSuchTask(usize),
+    NoMatch(String),
+    // The text picked out more than one task. Holds each candidate as it
+    // would be listed by coach task.
+    Ambiguous(String, Vec<String>),
+    NotOne,
+}
+
+impl fmt::Display for SelectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectError::NoSuchTask(ix) => write!(f, "{} is to large, no task found", ix),
+            SelectError::NoMatch(text) => write!(f, "no task matches {:?}", text),
+            SelectError::Ambiguous(text, candidates) => {
+                write!(f, "{:?} matches more than one task:", text)?;
+                for candidate in candidates {
+                    write!(f, "\n{}", candidate)?;
+                }
+                Ok(())
+            }
+            SelectError::NotOne => write!(f, "this command only works on one task at a time"),
+        }
+    }
+}
+
+impl Error for SelectError {}
+
+// Parses a list of task indexes and ranges of them, like 2-4,7. Returns
+// the ranges as they were written, or None if any part of s isn't an index
+// or a range, or an index is 0. Ranges aren't expanded until they're
+// checked against an entry's tasks, so a range like 1-99999999999 can't
+// run out of memory.
+pub fn parse_indexes(s: &str) -> Option<Vec<(usize, usize)>> {
+    let mut ranges = vec![];
+    for part in s.split(',') {
+        let part = part.trim();
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+            None => {
+                let ix: usize = part.parse().ok()?;
+                (ix, ix)
+            }
+        };
+        if first == 0 || last < first {
+            return None;
+        }
+        ranges.push((first, last));
+    }
+
+    Some(ranges)
+}
+
+// Reads s as a list of indexes if it is one, and as text otherwise.
+pub fn parse_selection(s: &str) -> Selection {
+    match parse_indexes(s) {
+        Some(indexes) => Selection::Indexes(indexes),
+        None => Selection::Text(s.to_string()),
+    }
+}
+
+// Whether the characters of needle all appear in haystack, in order,
+// ignoring whitespace in needle.
+fn fuzzy_matches(haystack: &str, needle: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|c| haystack.any(|h| h == c))
+}
+
+// Finds the one task that text refers to, ignoring case. A task whose
+// whole message is text wins, then a task whose message contains text,
+// and finally a task whose message contains the letters of text in order.
+// If the first of these that finds anything finds more than one task, the
+// choice is ambiguous.
+pub fn find_task(tasks: &[Task], text: &str) -> Result<usize, SelectError> {
+    let needle = text.to_lowercase();
+    let matchers: [&dyn Fn(&str) -> bool; 3] = [&|t| t == needle, &|t| t.contains(&needle), &|t| {
+        fuzzy_matches(t, &needle)
+    }];
+
+    for matcher in matchers.iter() {
+        let found: Vec<usize> = tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| matcher(&t.text().to_lowercase()))
+            .map(|(ix, _)| ix)
+            .collect();
+        match found[..] {
+            [] => continue,
+            [ix] => return Ok(ix),
+            _ => {
+                let candidates = found
+                    .iter()
+                    .map(|ix| format!("{}: {}", ix + 1, tasks[*ix]))
+                    .collect();
+                return Err(SelectError::Ambiguous(text.to_string(), candidates));
+            }
+        }
+    }
+
+    Err(SelectError::NoMatch(text.to_string()))
+}
+
+impl Selection {
+    // Returns the positions in tasks of the selected tasks, in order.
+    // Matching ignores case.
+    pub fn resolve(&self, tasks: &[Task]) -> Result<Vec<usize>, SelectError> {
+        match self {
+            Selection::Indexes(ranges) => {
+                let mut found = vec![];
+                for (first, last) in ranges {
+                    if *last > tasks.len() {
+                        let missing = (*first).max(tasks.len() + 1);
+                        return Err(SelectError::NoSuchTask(missing));
+                    }
+                    found.extend(first - 1..*last);
+                }
+                found.sort_unstable();
+                found.dedup();
+                Ok(found)
+            }
+            Selection::Matching(text) => {
+                let needle = text.to_lowercase();
+                let found: Vec<usize> = tasks
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, t)| t.text().to_lowercase().contains(&needle))
+                    .map(|(ix, _)| ix)
+                    .collect();
+                if found.is_empty() {
+                    return Err(SelectError::NoMatch(text.clone()));
+                }
+                Ok(found)
+            }
+            Selection::Text(text) => Ok(vec![find_task(tasks, text)?]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::as_no_newlines;
+
+    fn todo(s: &str) -> Task {
+        Task::Todo(as_no_newlines(s.to_string()).unwrap())
+    }
+
+    #[test]
+    fn test_parse_indexes() {
+        assert_eq!(Some(vec![(2, 4), (7, 7)]), parse_indexes("2-4,7"));
+        assert_eq!(Some(vec![(3, 3), (1, 1), (3, 3)]), parse_indexes("3, 1,3"));
+        assert_eq!(Some(vec![(1, 99999999999)]), parse_indexes("1-99999999999"));
+        assert_eq!(None, parse_indexes("0"));
+        assert_eq!(None, parse_indexes("4-2"));
+        assert_eq!(None, parse_indexes("2,"));
+        assert_eq!(None, parse_indexes("offsite"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tasks = vec![todo("plan Offsite"), todo("lunch"), todo("offsite travel")];
+        assert_eq!(
+            Ok(vec![0, 2]),
+            Selection::Matching(String::from("offsite")).resolve(&tasks)
+        );
+        assert_eq!(
+            Err(SelectError::NoMatch(String::from("dinner"))),
+            Selection::Matching(String::from("dinner")).resolve(&tasks)
+        );
+        assert_eq!(
+            Err(SelectError::NoSuchTask(4)),
+            Selection::Indexes(vec![(1, 1), (4, 4)]).resolve(&tasks)
+        );
+        assert_eq!(
+            Ok(vec![0, 1, 2]),
+            Selection::Indexes(vec![(3, 3), (1, 2), (2, 3)]).resolve(&tasks)
+        );
+        assert_eq!(
+            Err(SelectError::NoSuchTask(4)),
+            Selection::Indexes(vec![(1, 99999999999)]).resolve(&tasks)
+        );
+    }
+
+    #[test]
+    fn test_find_task() {
+        let tasks = vec![
+            todo("write README"),
+            todo("review readme changes"),
+            todo("lunch"),
+            todo("lunch with Sam"),
+        ];
+        assert_eq!(Ok(0), find_task(&tasks, "write"));
+        assert_eq!(Ok(2), find_task(&tasks, "Lunch"));
+        assert_eq!(Ok(3), find_task(&tasks, "lnch sam"));
+        assert_eq!(
+            Err(SelectError::Ambiguous(
+                String::from("readme"),
+                vec![
+                    String::from("1: TODO write README"),
+                    String::from("2: TODO review readme changes"),
+                ]
+            )),
+            find_task(&tasks, "readme")
+        );
+        assert_eq!(
+            Err(SelectError::NoMatch(String::from("dinner"))),
+            find_task(&tasks, "dinner")
+        );
+        assert_eq!(Selection::Indexes(vec![(2, 2)]), parse_selection("2"));
+        assert_eq!(
+            Selection::Text(String::from("README")),
+            parse_selection("README")
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use time::{Duration, PrimitiveDateTime};
+
+use crate::entry::Event;
+use crate::journal::{JournalEntry, DATE_FORMAT};
+use crate::query;
+use crate::report;
+
+// Interval events record time spent on something, like
+//
+//   * <2021-10-31 Sun 09:00>--<2021-10-31 Sun 10:30> client call #acme
+//
+// and a timesheet adds them up, by day, by week, or by the tags in them.
+
+// An interval event, and the entry it's in.
+pub struct Interval<'a> {
+    pub journal_entry: &'a JournalEntry,
+    pub begin: PrimitiveDateTime,
+    pub end: PrimitiveDateTime,
+    pub text: String,
+}
+
+impl Interval<'_> {
+    // Intervals that end before they begin count for nothing.
+    pub fn duration(&self) -> Duration {
+        (self.end - self.begin).max(Duration::ZERO)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Grouping {
+    Day,
+    // Weeks start on Monday, and are named after it.
+    Week,
+    // Intervals count toward every tag in them, so tag totals can add up
+    // to more than the time spent. Intervals without tags are grouped
+    // together as untagged.
+    Tag,
+}
+
+pub const UNTAGGED: &str = "untagged";
+
+// The total time spent on a day, week or tag.
+#[derive(Debug, PartialEq)]
+pub struct Total {
+    pub name: String,
+    pub duration: Duration,
+    pub intervals: usize,
+}
+
+// Every interval event in entries, in order.
+pub fn intervals(entries: &[JournalEntry]) -> Vec<Interval<'_>> {
+    let mut found = vec![];
+    for journal_entry in entries {
+        for event in &journal_entry.entry.events {
+            if let Event::Interval { begin, end, text } = event {
+                found.push(Interval {
+                    journal_entry,
+                    begin: *begin,
+                    end: *end,
+                    text: text.to_string(),
+                });
+            }
+        }
+    }
+
+    found
+}
+
+fn group_names(interval: &Interval, grouping: Grouping) -> Vec<String> {
+    match grouping {
+        Grouping::Day => vec![interval.journal_entry.name.clone()],
+        Grouping::Week => {
+            let (monday, _) =
+                report::period_dates(report::Period::Week, interval.journal_entry.date);
+            vec![monday.format(&DATE_FORMAT).unwrap()]
+        }
+        Grouping::Tag => {
+            let mut tags: Vec<String> = vec![];
+            for tag in query::tags(&interval.text) {
+                let tag = tag.to_lowercase();
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            if tags.is_empty() {
+                tags.push(String::from(UNTAGGED));
+            }
+            tags
+        }
+    }
+}
+
+// Adds up intervals by grouping. Days and weeks are in date order, and tags
+// are in the order they first turn up.
+pub fn totals(intervals: &[Interval], grouping: Grouping) -> Vec<Total> {
+    let mut totals: Vec<Total> = vec![];
+    for interval in intervals {
+        for name in group_names(interval, grouping) {
+            match totals.iter_mut().find(|t| t.name == name) {
+                Some(total) => {
+                    total.duration += interval.duration();
+                    total.intervals += 1;
+                }
+                None => totals.push(Total {
+                    name,
+                    duration: interval.duration(),
+                    intervals: 1,
+                }),
+            }
+        }
+    }
+
+    totals
+}
+
+// Returns each pair of intervals that overlap, as their positions in
+// intervals. Intervals that only touch, where one ends as the next begins,
+// don't overlap.
+pub fn overlaps(intervals: &[Interval]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..intervals.len()).collect();
+    order.sort_by_key(|ix| intervals[*ix].begin);
+
+    let mut found = vec![];
+    for (pos, a) in order.iter().enumerate() {
+        for b in order[pos + 1..].iter() {
+            if intervals[*b].begin >= intervals[*a].end {
+                break;
+            }
+            found.push((*a.min(b), *a.max(b)));
+        }
+    }
+    found.sort_unstable();
+
+    found
+}
+
+// Formats duration as hours and minutes, like 1:05.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.whole_minutes();
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+// Formats duration as hours, to two decimal places, like 1.08.
+pub fn format_hours(duration: Duration) -> String {
+    format!("{:.2}", duration.whole_minutes() as f64 / 60.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use crate::journal::entry_date;
+
+    fn journal_entry(name: &str, events: &str) -> JournalEntry {
+        JournalEntry {
+            name: name.to_string(),
+            date: entry_date(name).unwrap(),
+            entry: entry::parse(&format!("#coach\n{}\n\n{}", name, events)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_totals() {
+        let entries = vec![
+            journal_entry(
+                "2021-10-31",
+                "* <2021-10-31 Sun 09:00>--<2021-10-31 Sun 10:30> call #acme #Billing\n\
+                 * <2021-10-31 Sun 10:00>--<2021-10-31 Sun 10:15> standup\n\
+                 * <2021-10-31 Sun 10:30> lunch\n",
+            ),
+            journal_entry(
+                "2021-11-01",
+                "* <2021-11-01 Mon 13:00>--<2021-11-01 Mon 13:20> review #acme\n",
+            ),
+        ];
+        let found = intervals(&entries);
+        assert_eq!(3, found.len());
+
+        let summed = |grouping| -> Vec<(String, String, usize)> {
+            totals(&found, grouping)
+                .into_iter()
+                .map(|t| (t.name, format_duration(t.duration), t.intervals))
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                (String::from("2021-10-31"), String::from("1:45"), 2),
+                (String::from("2021-11-01"), String::from("0:20"), 1),
+            ],
+            summed(Grouping::Day)
+        );
+        assert_eq!(
+            vec![
+                (String::from("2021-10-25"), String::from("1:45"), 2),
+                (String::from("2021-11-01"), String::from("0:20"), 1),
+            ],
+            summed(Grouping::Week)
+        );
+        assert_eq!(
+            vec![
+                (String::from("acme"), String::from("1:50"), 2),
+                (String::from("billing"), String::from("1:30"), 1),
+                (String::from("untagged"), String::from("0:15"), 1),
+            ],
+            summed(Grouping::Tag)
+        );
+
+        assert_eq!(vec![(0, 1)], overlaps(&found));
+        assert_eq!("1.75", format_hours(Duration::minutes(105)));
+    }
+}
@@ -0,0 +1,531 @@
+use std::convert::{TryFrom, TryInto};
+use std::env;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use time::{Date, Month, OffsetDateTime, UtcOffset, Weekday};
+
+// Named time zones are looked up in the tz database's compiled files
+// (described in RFC 8536) rather than by pointing TZ at them, since
+// changing the environment isn't safe once other threads are running and
+// would leak into every process we start. Each file lists the moments the
+// zone's offset changes, and newer ones end with a POSIX TZ string giving
+// the rule for moments after the last of those.
+
+fn invalid(name: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("{} is not a valid tz database file", name),
+    )
+}
+
+// Returns where the file for a zone like America/Los_Angeles is, if the
+// name can't wander outside the tz database.
+pub fn path(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+        return None;
+    }
+
+    let zoneinfo_dir = env::var("TZDIR").unwrap_or_else(|_| String::from("/usr/share/zoneinfo"));
+    Some(PathBuf::from(zoneinfo_dir).join(name))
+}
+
+// Returns the offset from UTC that the named zone had at the given moment.
+pub fn offset_at(name: &str, when: OffsetDateTime) -> io::Result<UtcOffset> {
+    let filename = path(name).ok_or_else(|| invalid(name))?;
+    let data = fs::read(filename)?;
+    tzif_offset(&data, when.unix_timestamp()).ok_or_else(|| invalid(name))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.data.len() {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+struct Counts {
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+fn read_header(reader: &mut Reader) -> Option<(u8, Counts)> {
+    if reader.take(4)? != b"TZif" {
+        return None;
+    }
+    let version = reader.take(1)?[0];
+    reader.take(15)?;
+    let mut counts = [0; 6];
+    for count in counts.iter_mut() {
+        *count = reader.u32()? as usize;
+    }
+    let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts;
+    if typecnt == 0 {
+        return None;
+    }
+    Some((
+        version,
+        Counts {
+            isutcnt,
+            isstdcnt,
+            leapcnt,
+            timecnt,
+            typecnt,
+            charcnt,
+        },
+    ))
+}
+
+fn data_len(counts: &Counts, time_size: usize) -> Option<usize> {
+    let transitions = counts.timecnt.checked_mul(time_size + 1)?;
+    let types = counts.typecnt.checked_mul(6)?;
+    let leaps = counts.leapcnt.checked_mul(time_size + 4)?;
+    transitions
+        .checked_add(types)?
+        .checked_add(counts.charcnt)?
+        .checked_add(leaps)?
+        .checked_add(counts.isstdcnt)?
+        .checked_add(counts.isutcnt)
+}
+
+fn tzif_offset(data: &[u8], when: i64) -> Option<UtcOffset> {
+    let mut reader = Reader { data };
+    let (version, mut counts) = read_header(&mut reader)?;
+    let mut time_size = 4;
+    if version >= b'2' {
+        // The first block only has room for 32-bit times, so skip to the
+        // second, which repeats it all with 64-bit ones.
+        reader.take(data_len(&counts, 4)?)?;
+        counts = read_header(&mut reader)?.1;
+        time_size = 8;
+    }
+
+    let mut times = Vec::with_capacity(counts.timecnt);
+    for _ in 0..counts.timecnt {
+        let bytes = reader.take(time_size)?;
+        times.push(match time_size {
+            4 => i64::from(i32::from_be_bytes(bytes.try_into().ok()?)),
+            _ => i64::from_be_bytes(bytes.try_into().ok()?),
+        });
+    }
+    let type_indexes = reader.take(counts.timecnt)?;
+    let mut offsets = Vec::with_capacity(counts.typecnt);
+    for _ in 0..counts.typecnt {
+        offsets.push(reader.u32()? as i32);
+        reader.take(2)?;
+    }
+    reader.take(counts.charcnt)?;
+    reader.take(counts.leapcnt.checked_mul(time_size + 4)?)?;
+    reader.take(counts.isstdcnt)?;
+    reader.take(counts.isutcnt)?;
+
+    let footer = match version {
+        b'2'.. => {
+            let text = std::str::from_utf8(reader.data).ok()?;
+            let rule = text.strip_prefix('\n')?.split('\n').next()?;
+            match rule {
+                "" => None,
+                _ => Some(parse_rule(rule)?),
+            }
+        }
+        _ => None,
+    };
+
+    let seconds = match times.iter().rposition(|&t| t <= when) {
+        Some(i) if i + 1 == times.len() && footer.is_some() => footer?.offset_at(when)?,
+        Some(i) => *offsets.get(usize::from(type_indexes[i]))?,
+        None if times.is_empty() && footer.is_some() => footer?.offset_at(when)?,
+        None => offsets[0],
+    };
+    UtcOffset::from_whole_seconds(seconds).ok()
+}
+
+// When in the year daylight saving time starts or ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Day {
+    // Day 1 to 365, never counting February 29th.
+    Julian(u16),
+    // Day 0 to 365, counting February 29th in leap years.
+    ZeroBased(u16),
+    // The given weekday of the given week of the month, where week 5 is
+    // always the last one.
+    Weekday(Month, u8, Weekday),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Change {
+    day: Day,
+    // Seconds after local midnight.
+    time: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rule {
+    // Seconds east of UTC.
+    standard: i32,
+    daylight: Option<(i32, Change, Change)>,
+}
+
+// In the order POSIX numbers them.
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Sunday,
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+];
+
+struct RuleParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> RuleParser<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let len = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let (digits, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        digits.parse().ok()
+    }
+
+    fn name(&mut self) -> Option<()> {
+        let len = if self.eat('<') {
+            self.rest.find('>')? + 1
+        } else {
+            self.rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(self.rest.len())
+        };
+        if len < 3 {
+            return None;
+        }
+        self.rest = &self.rest[len..];
+        Some(())
+    }
+
+    // Parses a time like 2, -1:30 or 167:59:59 as seconds.
+    fn time(&mut self) -> Option<i64> {
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        let mut seconds = self.number()?.checked_mul(3600)?;
+        if self.eat(':') {
+            seconds = seconds.checked_add(self.number()?.checked_mul(60)?)?;
+            if self.eat(':') {
+                seconds = seconds.checked_add(self.number()?)?;
+            }
+        }
+        // Nothing is ever more than a week away.
+        if seconds > 168 * 3600 {
+            return None;
+        }
+        Some(sign * seconds)
+    }
+
+    // POSIX offsets count hours west of UTC, so they're negated here.
+    fn offset(&mut self) -> Option<i32> {
+        i32::try_from(-self.time()?).ok()
+    }
+
+    fn change(&mut self) -> Option<Change> {
+        let day = if self.eat('J') {
+            Day::Julian(
+                u16::try_from(self.number()?)
+                    .ok()
+                    .filter(|d| (1..=365).contains(d))?,
+            )
+        } else if self.eat('M') {
+            let month = Month::try_from(u8::try_from(self.number()?).ok()?).ok()?;
+            let week = self.eat('.').then(|| self.number())??;
+            let weekday = self.eat('.').then(|| self.number())??;
+            if !(1..=5).contains(&week) || weekday > 6 {
+                return None;
+            }
+            Day::Weekday(month, week as u8, WEEKDAYS[weekday as usize])
+        } else {
+            Day::ZeroBased(u16::try_from(self.number()?).ok().filter(|d| *d <= 365)?)
+        };
+        let time = if self.eat('/') { self.time()? } else { 7200 };
+        Some(Change { day, time })
+    }
+}
+
+fn parse_rule(s: &str) -> Option<Rule> {
+    let mut parser = RuleParser { rest: s };
+    parser.name()?;
+    let standard = parser.offset()?;
+    if parser.rest.is_empty() {
+        return Some(Rule {
+            standard,
+            daylight: None,
+        });
+    }
+
+    parser.name()?;
+    let daylight = match parser.rest.starts_with(',') {
+        true => standard + 3600,
+        false => parser.offset()?,
+    };
+    let (start, end) = if parser.eat(',') {
+        let start = parser.change()?;
+        if !parser.eat(',') {
+            return None;
+        }
+        (start, parser.change()?)
+    } else {
+        // With no rule, POSIX leaves it to the system; the US one is
+        // what everyone else picks.
+        (
+            Change {
+                day: Day::Weekday(Month::March, 2, Weekday::Sunday),
+                time: 7200,
+            },
+            Change {
+                day: Day::Weekday(Month::November, 1, Weekday::Sunday),
+                time: 7200,
+            },
+        )
+    };
+    if !parser.rest.is_empty() {
+        return None;
+    }
+    Some(Rule {
+        standard,
+        daylight: Some((daylight, start, end)),
+    })
+}
+
+impl Day {
+    fn date(self, year: i32) -> Option<Date> {
+        match self {
+            Day::Julian(day) => {
+                let leap = time::util::is_leap_year(year) && day >= 60;
+                Date::from_ordinal_date(year, day + u16::from(leap)).ok()
+            }
+            Day::ZeroBased(day) => Date::from_ordinal_date(year, day + 1).ok(),
+            Day::Weekday(month, week, weekday) => {
+                let first = Date::from_calendar_date(year, month, 1).ok()?;
+                let mut day = 1
+                    + (weekday.number_days_from_sunday() + 7
+                        - first.weekday().number_days_from_sunday())
+                        % 7
+                    + (week - 1) * 7;
+                if day > time::util::days_in_year_month(year, month) {
+                    day -= 7;
+                }
+                Date::from_calendar_date(year, month, day).ok()
+            }
+        }
+    }
+}
+
+impl Rule {
+    fn offset_at(self, when: i64) -> Option<i32> {
+        let (daylight, start, end) = match self.daylight {
+            None => return Some(self.standard),
+            Some(daylight) => daylight,
+        };
+        let local = OffsetDateTime::from_unix_timestamp(when + i64::from(self.standard)).ok()?;
+        let year = local.year();
+        // Changes happen at a local time, in whichever offset was in effect
+        // just before them.
+        let at = |change: Change, offset: i32| -> Option<i64> {
+            let midnight = change.day.date(year)?.midnight().assume_utc();
+            Some(midnight.unix_timestamp() + change.time - i64::from(offset))
+        };
+        let start = at(start, self.standard)?;
+        let end = at(end, daylight)?;
+        let in_daylight = match start < end {
+            true => start <= when && when < end,
+            // In the southern hemisphere, it spans the new year.
+            false => !(end <= when && when < start),
+        };
+        Some(if in_daylight { daylight } else { self.standard })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn timestamp(when: OffsetDateTime) -> i64 {
+        when.unix_timestamp()
+    }
+
+    // Builds a version 2 file, with the given transitions and the offset
+    // each one switches to, ending with the given rule.
+    fn tzif(transitions: &[(i64, i32)], offsets: &[i32], rule: &str) -> Vec<u8> {
+        fn header(out: &mut Vec<u8>, timecnt: usize, typecnt: usize) {
+            out.extend(b"TZif2");
+            out.extend([0; 15]);
+            for count in [0, 0, 0, timecnt, typecnt, typecnt * 4] {
+                out.extend((count as u32).to_be_bytes());
+            }
+        }
+        fn block(out: &mut Vec<u8>, transitions: &[(i64, i32)], offsets: &[i32], wide: bool) {
+            for (t, _) in transitions {
+                match wide {
+                    true => out.extend(t.to_be_bytes()),
+                    false => out.extend((*t as i32).to_be_bytes()),
+                }
+            }
+            for (_, offset) in transitions {
+                out.push(offsets.iter().position(|o| o == offset).unwrap() as u8);
+            }
+            for (i, offset) in offsets.iter().enumerate() {
+                out.extend(offset.to_be_bytes());
+                out.extend([0, (i * 4) as u8]);
+            }
+            for _ in offsets {
+                out.extend(b"XYZ\0");
+            }
+        }
+
+        let mut out = vec![];
+        header(&mut out, transitions.len(), offsets.len());
+        block(&mut out, transitions, offsets, false);
+        header(&mut out, transitions.len(), offsets.len());
+        block(&mut out, transitions, offsets, true);
+        out.extend(format!("\n{}\n", rule).as_bytes());
+        out
+    }
+
+    #[test]
+    fn test_transitions() {
+        let data = tzif(
+            &[
+                (timestamp(datetime!(2021-03-14 10:00 UTC)), -7 * 3600),
+                (timestamp(datetime!(2021-11-07 09:00 UTC)), -8 * 3600),
+            ],
+            &[-8 * 3600, -7 * 3600],
+            "",
+        );
+        let at = |when: OffsetDateTime| tzif_offset(&data, timestamp(when));
+        assert_eq!(
+            UtcOffset::from_hms(-8, 0, 0).ok(),
+            at(datetime!(2021-01-01 00:00 UTC))
+        );
+        assert_eq!(
+            UtcOffset::from_hms(-7, 0, 0).ok(),
+            at(datetime!(2021-03-14 10:00 UTC))
+        );
+        assert_eq!(
+            UtcOffset::from_hms(-8, 0, 0).ok(),
+            at(datetime!(2022-07-01 00:00 UTC))
+        );
+        assert_eq!(None, tzif_offset(b"TZif2", 0));
+        assert_eq!(None, tzif_offset(&data[..data.len() / 2], 0));
+    }
+
+    #[test]
+    fn test_rule_after_last_transition() {
+        let data = tzif(
+            &[(timestamp(datetime!(2000-01-01 00:00 UTC)), -8 * 3600)],
+            &[-8 * 3600],
+            "PST8PDT,M3.2.0,M11.1.0",
+        );
+        let at = |when: OffsetDateTime| tzif_offset(&data, timestamp(when));
+        assert_eq!(
+            UtcOffset::from_hms(-8, 0, 0).ok(),
+            at(datetime!(2040-03-11 09:59 UTC))
+        );
+        assert_eq!(
+            UtcOffset::from_hms(-7, 0, 0).ok(),
+            at(datetime!(2040-03-11 10:00 UTC))
+        );
+        assert_eq!(
+            UtcOffset::from_hms(-7, 0, 0).ok(),
+            at(datetime!(2040-11-04 08:59 UTC))
+        );
+        assert_eq!(
+            UtcOffset::from_hms(-8, 0, 0).ok(),
+            at(datetime!(2040-11-04 09:00 UTC))
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let sydney = parse_rule("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        let at = |rule: Rule, when: OffsetDateTime| rule.offset_at(timestamp(when));
+        assert_eq!(Some(11 * 3600), at(sydney, datetime!(2021-01-15 00:00 UTC)));
+        assert_eq!(Some(10 * 3600), at(sydney, datetime!(2021-07-15 00:00 UTC)));
+
+        let india = parse_rule("IST-5:30").unwrap();
+        assert_eq!(
+            Some(5 * 3600 + 1800),
+            at(india, datetime!(2021-07-15 00:00 UTC))
+        );
+
+        let quoted = parse_rule("<-03>3").unwrap();
+        assert_eq!(Some(-3 * 3600), at(quoted, datetime!(2021-07-15 00:00 UTC)));
+
+        assert_eq!(
+            Some(datetime!(2021-03-01 00:00 UTC).date()),
+            Day::Julian(60).date(2021)
+        );
+        assert_eq!(
+            Some(datetime!(2024-03-01 00:00 UTC).date()),
+            Day::Julian(60).date(2024)
+        );
+        assert_eq!(
+            Some(datetime!(2021-05-31 00:00 UTC).date()),
+            Day::Weekday(Month::May, 5, Weekday::Monday).date(2021)
+        );
+
+        assert_eq!(None, parse_rule("PST"));
+        assert_eq!(None, parse_rule("PST8PDT,M3.2.0"));
+        assert_eq!(None, parse_rule("PST8PDT,M13.2.0,M11.1.0"));
+    }
+
+    #[test]
+    fn test_system_zone() {
+        // Only where the system has a tz database to check against.
+        if !path("America/Los_Angeles").is_some_and(|p| p.is_file()) {
+            return;
+        }
+        assert_eq!(
+            UtcOffset::from_hms(-7, 0, 0).unwrap(),
+            offset_at("America/Los_Angeles", datetime!(2021-07-01 12:00 UTC)).unwrap()
+        );
+        assert_eq!(
+            UtcOffset::from_hms(-8, 0, 0).unwrap(),
+            offset_at("America/Los_Angeles", datetime!(2021-12-01 12:00 UTC)).unwrap()
+        );
+        assert!(offset_at("../etc/passwd", datetime!(2021-12-01 12:00 UTC)).is_err());
+    }
+}